@@ -23,3 +23,4 @@ mov bp, word [1006]
 ; dx: 0x000a
 ; bp: 0x0004
 ; ip: 0x0030
+; [1000]: 01 00 02 00 0a 00 04 00
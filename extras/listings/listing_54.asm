@@ -29,3 +29,13 @@ y_loop_start:
 	add dx, 1
 	cmp dx, 64
 	jnz y_loop_start
+
+; ANSWER
+; cx: 0x0040
+; dx: 0x0040
+; bp: 0x4100
+; ip: 0x0026
+; flags: PZ
+; [0x100]: 00 00 00 ff 01 00 00 ff
+; [0x40f8]: 3e 00 3f ff 3f 00 3f ff
+; memory hash: 0x681f6419e817d313
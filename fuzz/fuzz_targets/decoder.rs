@@ -0,0 +1,9 @@
+#![no_main]
+
+use computer_enhance_rust::intel8086::fuzzing::check_decoder_invariants;
+use libfuzzer_sys::fuzz_target;
+
+// cargo +nightly fuzz run decoder
+fuzz_target!(|bytes: &[u8]| {
+    check_decoder_invariants(bytes);
+});
@@ -1,6 +1,10 @@
 use clap::Parser;
-use computer_enhance_rust::{self, args, intel8086};
-use log::info;
+use computer_enhance_rust::{
+    self, args, intel8086, intel8086::args::OutputFormat, intel8086::constants::AddressAnnotations,
+    intel8086::estimate::CycleReport, intel8086::DisassemblyResult, nasm::assemble_listing,
+};
+use log::{info, warn};
+use std::path::Path;
 
 #[derive(Parser)]
 struct Args {
@@ -13,15 +17,104 @@ struct Args {
     intel: intel8086::args::IntelArgs,
 }
 
+// The JSON output. The cycle estimate is only there with --cycles.
+#[derive(serde::Serialize)]
+struct JsonOutput<'a> {
+    #[serde(flatten)]
+    result: &'a DisassemblyResult,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cycles: Option<&'a CycleReport>,
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
     computer_enhance_rust::args::evaluate_log(&args.base);
 
-    let contents = std::fs::read(&args.input)?;
-    let instructions = intel8086::disassemble(&contents)?;
+    // Listings are assembled first (or taken from their precompiled bytes).
+    let contents = if Path::new(&args.input)
+        .extension()
+        .is_some_and(|e| e == "asm")
+    {
+        let temp_dir = tempfile::TempDir::new()?;
+        assemble_listing(temp_dir.path(), &args.input)?
+    } else {
+        std::fs::read(&args.input)?
+    };
+
+    // Following the control flow is only done once, for everything that needs it.
+    let traversal = (args.intel.recursive || args.intel.constants || args.intel.cycles)
+        .then(|| intel8086::traversal::disassemble_recursive(&contents, &[0]));
+    let cycles = match &traversal {
+        Some(traversal) if args.intel.cycles => {
+            Some(intel8086::estimate::estimate_traversal(traversal))
+        }
+        _ => None,
+    };
+
+    let formatter = intel8086::syntax::formatter(&args.intel.syntax);
+    let annotated;
+    let formatter = match &traversal {
+        Some(traversal) if args.intel.constants => {
+            annotated = AddressAnnotations::new(formatter.as_ref(), traversal);
+            &annotated
+        }
+        _ => formatter.as_ref(),
+    };
+
+    let result = if let (true, Some(traversal)) = (args.intel.recursive, traversal) {
+        info!("Basic blocks:\n{}", traversal.cfg_string());
+        DisassemblyResult {
+            instructions: traversal.instructions,
+            data: traversal.data,
+            diagnostics: vec![],
+        }
+    } else if args.intel.recover {
+        let result = intel8086::disassemble_recovering(&contents);
+        for diagnostic in &result.diagnostics {
+            warn!("{}", diagnostic);
+        }
+        result
+    } else {
+        DisassemblyResult {
+            instructions: intel8086::disassemble(&contents)?,
+            data: vec![],
+            diagnostics: vec![],
+        }
+    };
+
+    let DisassemblyResult {
+        instructions, data, ..
+    } = &result;
+
+    let output = match args.intel.format {
+        OutputFormat::Asm => {
+            // The recursive traversal knows the jump targets, so it is always labelled.
+            if args.intel.recursive || !data.is_empty() {
+                intel8086::traversal::to_asm_with_data(instructions, data, formatter)
+            } else if args.intel.labels {
+                intel8086::labels::to_asm_with_labels(instructions, formatter)
+            } else {
+                intel8086::syntax::to_asm_with_syntax(instructions, formatter)
+            }
+        }
+        OutputFormat::Listing => {
+            intel8086::listing::to_listing(args.intel.segment, instructions, data, formatter)
+        }
+        OutputFormat::Json => {
+            // Printed directly so it can be piped into other tools.
+            let output = JsonOutput {
+                result: &result,
+                cycles: cycles.as_ref(),
+            };
+            println!("{}", serde_json::to_string_pretty(&output)?);
+            return Ok(());
+        }
+    };
+    info!("{}", output);
 
-    let asm = intel8086::to_asm(&instructions);
-    info!("{}", asm);
+    if let Some(report) = &cycles {
+        info!("Cycle estimate:\n{}", report);
+    }
 
     Ok(())
 }
@@ -6,6 +6,7 @@ use std::{
     fs::File,
     io::{BufReader, Error, ErrorKind},
 };
+use thiserror::Error;
 
 #[derive(Parser)]
 struct Args {
@@ -21,6 +22,23 @@ struct Args {
     json: json::args::JsonArgs,
 }
 
+// The input is not the JSON we expected. main prints what it returns with Debug, which would bury
+// where in the file it went wrong, so Debug prints the message too.
+#[derive(Error)]
+enum InputError {
+    #[error("{0}: {1}")]
+    Json(String, #[source] json::error::JsonError),
+
+    #[error("{0}: {1}")]
+    Stream(String, #[source] Error),
+}
+
+impl std::fmt::Debug for InputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     init_profiler();
 
@@ -31,8 +49,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         computer_enhance_rust::args::evaluate_log(&args.base);
     }
 
+    // The timings are printed even when the input is bad.
+    let result = run(args);
+
+    shutdown_profiler();
+
+    print_timings();
+
+    result
+}
+
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
     let filename = args.input;
     let mut coords: Vec<Coord> = vec![];
+    // The stream parser averages as it goes, so there are no coords left to average.
+    let mut streamed_average: Option<f64> = None;
 
     {
         match args.json.json_parser {
@@ -47,11 +78,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     let _size = std::fs::metadata(&filename)?.len();
                     profile_block!("Read File", _size);
 
-                    bytes = std::fs::read(filename)?;
+                    bytes = std::fs::read(&filename)?;
                     info!("Input size: {}", bytes.len());
                 }
 
-                let json::JsonValue::Array(array) = json::parse(&bytes)? else {
+                let value =
+                    json::parse(&bytes).map_err(|e| InputError::Json(filename.clone(), e))?;
+
+                let json::JsonValue::Array(array) = value else {
                     return Err(Box::new(Error::new(ErrorKind::InvalidData, "Expected array")));
                 };
 
@@ -80,18 +114,25 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }
                 }
             }
+            json::args::JsonParser::Stream => {
+                let file = File::open(&filename)?;
+                let (average, count) =
+                    haversine_average_streamed(file, args.haversine.earth_radius)
+                        .map_err(|e| InputError::Stream(filename.clone(), e))?;
+                info!("Pair count: {}", count);
+                streamed_average = Some(average);
+            }
         }
     }
 
     {
-        let average = haversine_average(&coords, args.haversine.earth_radius);
+        let average = match streamed_average {
+            Some(average) => average,
+            None => haversine_average(&coords, args.haversine.earth_radius),
+        };
         info!("Havensine average: {:?}", average);
     }
 
-    shutdown_profiler();
-
-    print_timings();
-
     Ok(())
 }
 
@@ -1,6 +1,14 @@
 use anyhow::anyhow;
 use clap::Parser;
-use computer_enhance_rust::{self, args, intel8086, nasm::run_nasm};
+use computer_enhance_rust::{
+    self, args, intel8086,
+    intel8086::args::{OutputFormat, ScreenFormat},
+    intel8086::coverage::{Coverage, CoverageReport},
+    intel8086::debugger::Debugger,
+    intel8086::SimulationResult,
+    nasm::assemble_listing,
+};
+use std::io::Write;
 use std::path::Path;
 
 #[derive(Parser)]
@@ -14,12 +22,71 @@ struct Args {
     intel: intel8086::args::IntelArgs,
 }
 
+// The JSON output. The screen and the coverage are only there when asked for.
+#[derive(serde::Serialize)]
+struct JsonOutput<'a> {
+    #[serde(flatten)]
+    result: &'a SimulationResult,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    screen: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    coverage: Option<String>,
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
     computer_enhance_rust::args::evaluate_log(&args.base);
 
-    let bytes = run_nasm(Path::new("."), &args.input)?;
-    let result = intel8086::simulate(&bytes)?;
+    let temp_dir = tempfile::TempDir::new()?;
+    let bytes = assemble_listing(temp_dir.path(), &args.input)?;
+    let mut options = intel8086::SimulationOptions {
+        segment: args.intel.segment,
+        io: intel8086::io::IoBus::pc(args.intel.keys.clone()),
+        vectors: args.intel.vector.clone(),
+        interrupts: args.intel.interrupt.clone(),
+        on_instruction: None,
+        max_instructions: None,
+    };
+    // With JSON on stdout everything else goes to stderr, so the output can still be piped.
+    let json = matches!(args.intel.format, OutputFormat::Json);
+    if args.intel.debugger {
+        let output: Box<dyn Write> = if json {
+            Box::new(std::io::stderr())
+        } else {
+            Box::new(std::io::stdout())
+        };
+        let mut debugger = Debugger::new(std::io::stdin().lock(), output);
+        options.on_instruction =
+            Some(Box::new(move |cpu, instruction| debugger.after_instruction(cpu, instruction)));
+    }
+    let result = intel8086::simulate_with(&bytes, options)?;
+
+    let screen = args.intel.screen.as_ref().map(|screen| {
+        let colors = matches!(screen, ScreenFormat::Ansi);
+        intel8086::video::render_text_buffer(result.cpu.get_memory(), colors)
+    });
+
+    let coverage = args.intel.coverage.then(|| {
+        let start = intel8086::cpu::physical_address(args.intel.segment, 0);
+        let coverage = Coverage::from_executed(&result.executed_instructions, start);
+        let report = CoverageReport::new(&bytes, coverage);
+        let formatter = intel8086::syntax::formatter(&args.intel.syntax);
+        let listing = report.to_listing(args.intel.segment, formatter.as_ref());
+        format!("{}{}", listing, report)
+    });
+
+    if json {
+        let output = JsonOutput {
+            result: &result,
+            screen,
+            coverage,
+        };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        for text in screen.iter().chain(coverage.iter()) {
+            print!("{}", text);
+        }
+    }
 
     if args.intel.dump_memory {
         let filename = Path::new(&args.input)
@@ -30,7 +97,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         let memory = result.cpu.get_memory();
         std::fs::write(&out, &memory)?;
 
-        println!("Wrote result dump to {}", out);
+        if json {
+            eprintln!("Wrote result dump to {}", out);
+        } else {
+            println!("Wrote result dump to {}", out);
+        }
     }
 
     Ok(())
@@ -0,0 +1,72 @@
+use clap::Parser;
+use computer_enhance_rust::{self, args, intel8086::batch::*};
+use std::path::Path;
+
+#[derive(Parser)]
+struct Args {
+    /// Programs to simulate and what they should end with. See intel8086::batch for the format.
+    pub manifest: String,
+
+    /// How many programs to simulate at the same time. Defaults to the number of cores.
+    #[arg(long)]
+    pub jobs: Option<usize>,
+
+    #[command(flatten)]
+    base: args::BaseArgs,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    // The simulation logs every instruction at info, which would bury the table.
+    if args.base.debug {
+        computer_enhance_rust::args::evaluate_log(&args.base);
+    } else if !args.base.silent {
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    }
+
+    let manifest = std::fs::read_to_string(&args.manifest)?;
+    let dir = Path::new(&args.manifest).parent().unwrap_or(Path::new("."));
+    let entries = parse_manifest(&manifest, dir);
+
+    let jobs = match args.jobs {
+        Some(jobs) => jobs,
+        None => std::thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    let outcomes = run_batch(&entries, jobs);
+
+    let width = outcomes
+        .iter()
+        .map(|o| o.program.display().to_string().len())
+        .max()
+        .unwrap_or(0);
+
+    let mut failed = 0;
+    for outcome in &outcomes {
+        let program = outcome.program.display().to_string();
+        match &outcome.result {
+            Ok((cycles, mismatches)) => {
+                let status = if mismatches.is_empty() {
+                    "PASS"
+                } else {
+                    "FAIL"
+                };
+                println!("{}  {:<width$}  {} cycles", status, program, cycles);
+                for mismatch in mismatches {
+                    println!("        {}", mismatch.replace('\n', "\n        "));
+                }
+            }
+            Err(e) => println!("ERROR {:<width$}  {}", program, e),
+        }
+
+        if !outcome.passed() {
+            failed += 1;
+        }
+    }
+
+    println!("{} programs: {} passed, {} failed", outcomes.len(), outcomes.len() - failed, failed);
+
+    if failed > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -5,9 +5,11 @@ use log::{debug, info};
 use rand::Rng;
 use rand::{rngs::StdRng, SeedableRng};
 use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind, Read};
 use std::ops::Range;
 use std::time::Instant;
 
+use crate::json::stream::*;
 use crate::profile_function;
 
 #[derive(Debug, Clone, ValueEnum)]
@@ -169,6 +171,84 @@ pub fn haversine_average(coords: &[Coord], radius: f64) -> f64 {
     average
 }
 
+// Like |haversine_average|, but reading the pairs from JSON one at a time, so the input can be
+// bigger than memory. Returns the average and how many pairs there were.
+pub fn haversine_average_streamed<R: Read>(reader: R, radius: f64) -> Result<(f64, usize), Error> {
+    profile_function!();
+
+    let mut parser = StreamParser::new(reader);
+    if !matches!(parser.next_event().map_err(invalid)?, Some(Event::StartArray)) {
+        return Err(invalid("Expected array"));
+    }
+
+    let mut sum: f64 = 0.0;
+    let mut count: usize = 0;
+    loop {
+        match parser.next_event().map_err(invalid)? {
+            Some(Event::EndArray) => break,
+            Some(Event::StartObject) => {}
+            _ => return Err(invalid(format!("entry {}: Expected object", count))),
+        }
+
+        let coord =
+            read_coord(&mut parser).map_err(|e| invalid(format!("entry {}: {}", count, e)))?;
+        sum += reference_haversine(&coord, radius);
+        count += 1;
+    }
+
+    // Makes sure nothing comes after the array.
+    parser.next_event().map_err(invalid)?;
+
+    let average = if count == 0 { 0.0 } else { sum / count as f64 };
+    Ok((average, count))
+}
+
+const COORD_KEYS: [&str; 4] = ["x0", "y0", "x1", "y1"];
+
+// The rest of an object whose StartObject was already read. Other keys are ignored, like serde does.
+fn read_coord<R: Read>(parser: &mut StreamParser<R>) -> Result<Coord, Error> {
+    let mut values: [Option<f64>; 4] = [None; 4];
+
+    loop {
+        let index = match parser.next_event().map_err(invalid)? {
+            Some(Event::EndObject) => break,
+            Some(Event::Key(key)) => COORD_KEYS.iter().position(|k| *k == key),
+            _ => return Err(invalid("Expected key")),
+        };
+
+        let event = parser.next_event().map_err(invalid)?;
+        match index {
+            Some(index) => {
+                let Some(Event::Number(n)) = event else {
+                    return Err(invalid(format!("{}: Expected number", COORD_KEYS[index])));
+                };
+                values[index] = Some(n);
+            }
+            None => {
+                if matches!(event, Some(Event::StartArray | Event::StartObject)) {
+                    let depth = parser.depth();
+                    while parser.depth() >= depth {
+                        parser.next_event().map_err(invalid)?;
+                    }
+                }
+            }
+        }
+    }
+
+    let [Some(x0), Some(y0), Some(x1), Some(y1)] = values else {
+        let missing = values.iter().position(Option::is_none).unwrap();
+        return Err(invalid(format!("Missing {}", COORD_KEYS[missing])));
+    };
+    Ok(Coord { x0, y0, x1, y1 })
+}
+
+fn invalid<E>(e: E) -> Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    Error::new(ErrorKind::InvalidData, e)
+}
+
 // This haversine implementation assumes a |coord| in degrees and |radius| in km.
 //
 // This is not meant to be a "good" way to calculate the Haversine distance.
@@ -193,3 +273,34 @@ pub fn generate_rng(seed: u64) -> StdRng {
         StdRng::from_entropy()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_cargo_root;
+
+    #[test]
+    fn test_streamed_average() {
+        let path = get_cargo_root()
+            .unwrap()
+            .join("extras/json/coords_100.json");
+        let bytes = std::fs::read(path).unwrap();
+
+        let coords: Vec<Coord> = serde_json::from_slice(&bytes).unwrap();
+        let want = haversine_average(&coords, 6372.8);
+        assert_eq!(haversine_average_streamed(&bytes[..], 6372.8).unwrap(), (want, 100));
+
+        let bytes = br#"[{"x0": 1, "extra": [{}], "y0": 2, "x1": 3, "y1": 4}]"#;
+        assert_eq!(haversine_average_streamed(&bytes[..], 1.0).unwrap().1, 1);
+
+        for bytes in [
+            &b"{}"[..],
+            b"[1]",
+            br#"[{"x0": 1}]"#,
+            br#"[{"x0": "1"}]"#,
+            b"[] []",
+        ] {
+            assert!(haversine_average_streamed(bytes, 1.0).is_err(), "{:?}", bytes);
+        }
+    }
+}
@@ -1,7 +1,110 @@
 pub use clap::Parser;
+use clap::ValueEnum;
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum OutputFormat {
+    Asm,
+    Listing,
+    Json,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum ScreenFormat {
+    Plain,
+    Ansi,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum AsmSyntax {
+    Nasm,
+    Masm,
+    Att,
+}
 
 #[derive(Parser, Debug)]
 pub struct IntelArgs {
     #[arg(long)]
     pub dump_memory: bool,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Asm)]
+    pub format: OutputFormat,
+
+    #[arg(long, value_enum, default_value_t = AsmSyntax::Nasm)]
+    pub syntax: AsmSyntax,
+
+    /// Print jump targets as labels instead of $ offsets.
+    #[arg(long)]
+    pub labels: bool,
+
+    /// Follow the control flow from the start of the program, printing unreached bytes as data.
+    #[arg(long)]
+    pub recursive: bool,
+
+    /// Keep going on bytes that cannot be decoded, printing them as data.
+    #[arg(long)]
+    pub recover: bool,
+
+    /// Comment memory operands with their effective address, when the registers it uses hold
+    /// known constants.
+    #[arg(long)]
+    pub constants: bool,
+
+    /// Estimate the cycles of every basic block and loop without running the program.
+    #[arg(long)]
+    pub cycles: bool,
+
+    /// Scancodes the simulated keyboard hands out, in hex (e.g. --keys 1E 9E).
+    #[arg(long, num_args = 1.., value_parser = parse_scancode)]
+    pub keys: Vec<u8>,
+
+    /// Print the CGA text buffer (B800:0000) once the simulation ends.
+    #[arg(long, value_enum)]
+    pub screen: Option<ScreenFormat>,
+
+    /// Print how many times each instruction ran, and the ranges that never did.
+    #[arg(long)]
+    pub coverage: bool,
+
+    /// Stop after every instruction and read commands from stdin: step, continue, regs, and
+    /// screen or ansi to print the CGA text buffer.
+    #[arg(long)]
+    pub debugger: bool,
+
+    /// The segment the program is loaded at, in hex. Listings show it, and the simulator loads the
+    /// program there instead of over the interrupt vector table at 0.
+    #[arg(long, value_parser = parse_hex_u16, default_value = "0")]
+    pub segment: u16,
+
+    /// Point an interrupt vector at a handler in the program, as VECTOR:OFFSET in hex (e.g.
+    /// --vector 8:1A).
+    #[arg(long, value_parser = parse_vector)]
+    pub vector: Vec<(u8, u16)>,
+
+    /// Raise an external interrupt after some instructions ran, as COUNT:VECTOR with the vector in
+    /// hex (e.g. --interrupt 100:8).
+    #[arg(long, value_parser = parse_interrupt)]
+    pub interrupt: Vec<(usize, u8)>,
+}
+
+fn parse_scancode(value: &str) -> Result<u8, String> {
+    u8::from_str_radix(value, 16).map_err(|e| format!("{}: {}", value, e))
+}
+
+fn parse_hex_u16(value: &str) -> Result<u16, String> {
+    u16::from_str_radix(value, 16).map_err(|e| format!("{}: {}", value, e))
+}
+
+fn parse_vector(value: &str) -> Result<(u8, u16), String> {
+    let (vector, offset) = value
+        .split_once(':')
+        .ok_or(format!("{}: expected VECTOR:OFFSET", value))?;
+    Ok((parse_scancode(vector)?, parse_hex_u16(offset)?))
+}
+
+fn parse_interrupt(value: &str) -> Result<(usize, u8), String> {
+    let (count, vector) = value
+        .split_once(':')
+        .ok_or(format!("{}: expected COUNT:VECTOR", value))?;
+    let count = count.parse().map_err(|e| format!("{}: {}", value, e))?;
+    Ok((count, parse_scancode(vector)?))
 }
@@ -0,0 +1,225 @@
+use super::error::IntelError;
+use super::expectations::*;
+use super::SimulationOptions;
+use crate::nasm::assemble_listing;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+// Runs many programs and checks each one against what it should end with.
+//
+// The manifest has one program per line, relative to the manifest. Listings (.asm) are assembled,
+// or taken from their precompiled .bin, and checked against their "; ANSWER" block. The
+// "; name: value" lines after a program add expectations to it, which is the only way to give
+// them to raw binaries. Lines starting with # are comments.
+//
+// # Listings carry their own answer.
+// listing_56.asm
+//
+// loop.bin
+// ; cx: 0x0000
+// ; cycles: 48
+
+// Programs still running after this many instructions are stopped, so one that never ends
+// doesn't hold up the batch.
+pub const MAX_INSTRUCTIONS: usize = 1_000_000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct BatchEntry {
+    pub program: PathBuf,
+    pub expectations: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct BatchOutcome {
+    pub program: PathBuf,
+    // The cycles it ran for and what didn't match, or why it couldn't run.
+    pub result: Result<(usize, Vec<String>), IntelError>,
+}
+
+impl BatchOutcome {
+    pub fn passed(&self) -> bool {
+        matches!(&self.result, Ok((_, mismatches)) if mismatches.is_empty())
+    }
+}
+
+pub fn parse_manifest(manifest: &str, dir: &Path) -> Vec<BatchEntry> {
+    let mut entries: Vec<BatchEntry> = vec![];
+
+    for line in manifest.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with(';') {
+            if let Some(entry) = entries.last_mut() {
+                entry.expectations.push(line.to_string());
+            }
+            continue;
+        }
+
+        entries.push(BatchEntry {
+            program: dir.join(line),
+            expectations: vec![],
+        });
+    }
+
+    entries
+}
+
+// A program that makes the simulator panic only fails its own entry.
+pub fn run_entry(entry: &BatchEntry) -> BatchOutcome {
+    BatchOutcome {
+        program: entry.program.clone(),
+        result: catch_panic(|| simulate_entry(entry)),
+    }
+}
+
+// Runs the entries on |jobs| threads. The outcomes are in the order of |entries|.
+pub fn run_batch(entries: &[BatchEntry], jobs: usize) -> Vec<BatchOutcome> {
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, entries.len().max(1)) {
+            let sender = sender.clone();
+            let next = &next;
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(entry) = entries.get(index) else {
+                    break;
+                };
+                if sender.send((index, run_entry(entry))).is_err() {
+                    break;
+                }
+            });
+        }
+    });
+    drop(sender);
+
+    let mut outcomes: Vec<(usize, BatchOutcome)> = receiver.into_iter().collect();
+    outcomes.sort_by_key(|(index, _)| *index);
+    outcomes.into_iter().map(|(_, outcome)| outcome).collect()
+}
+
+// HELPERS -----------------------------------------------------------------------------------------
+
+fn simulate_entry(entry: &BatchEntry) -> Result<(usize, Vec<String>), IntelError> {
+    let is_listing = entry.program.extension().is_some_and(|e| e == "asm");
+
+    let (bytes, listing) = if is_listing {
+        let temp_dir = tempfile::TempDir::new()?;
+        let bytes = assemble_listing(temp_dir.path(), &entry.program)?;
+        (bytes, std::fs::read_to_string(&entry.program)?)
+    } else {
+        (std::fs::read(&entry.program)?, String::new())
+    };
+
+    let lines = answer_lines(&listing).chain(entry.expectations.iter().map(String::as_str));
+    let want = parse_expectations(lines)?;
+
+    let options = SimulationOptions {
+        max_instructions: Some(MAX_INSTRUCTIONS),
+        ..Default::default()
+    };
+    let result = super::simulate_with(&bytes, options)?;
+    Ok((result.cycles, check(&want, &result)))
+}
+
+fn catch_panic<T>(f: impl FnOnce() -> Result<T, IntelError>) -> Result<T, IntelError> {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic| {
+        let message = match panic.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => panic
+                .downcast_ref::<String>()
+                .cloned()
+                .unwrap_or_else(|| "unknown panic".to_string()),
+        };
+        Err(IntelError::Panicked(message))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = "# comment\n\
+                        listing_56.asm\n\
+                        \n\
+                        loop.bin\n\
+                        ; cx: 0x0000\n";
+
+        let entries = parse_manifest(manifest, Path::new("programs"));
+        assert_eq!(
+            entries,
+            vec![
+                BatchEntry {
+                    program: PathBuf::from("programs/listing_56.asm"),
+                    expectations: vec![],
+                },
+                BatchEntry {
+                    program: PathBuf::from("programs/loop.bin"),
+                    expectations: vec!["; cx: 0x0000".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_batch() {
+        let dir = tempfile::TempDir::new().unwrap();
+        // mov cx, 3
+        std::fs::write(dir.path().join("mov.bin"), [0xB9, 0x03, 0x00]).unwrap();
+
+        let manifest = "mov.bin\n\
+                        ; cx: 0x0003\n\
+                        ; ip: 0x0003\n\
+                        mov.bin\n\
+                        ; cx: 0x0004\n\
+                        missing.bin\n";
+        let entries = parse_manifest(manifest, dir.path());
+
+        let outcomes = run_batch(&entries, 2);
+        assert_eq!(outcomes.len(), 3);
+        assert!(outcomes[0].passed());
+        assert!(matches!(&outcomes[0].result, Ok((4, _))));
+
+        assert!(!outcomes[1].passed());
+        let Ok((_, mismatches)) = &outcomes[1].result else {
+            panic!("{:?}", outcomes[1]);
+        };
+        assert_eq!(mismatches[0], "cx: want 0x0004 (4) got 0x0003 (3)");
+
+        assert!(matches!(outcomes[2].result, Err(IntelError::Io(_))));
+    }
+
+    #[test]
+    fn test_run_batch_stops_endless_programs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        // jmp $
+        std::fs::write(dir.path().join("spin.bin"), [0xEB, 0xFE]).unwrap();
+        // mov cx, 3
+        std::fs::write(dir.path().join("mov.bin"), [0xB9, 0x03, 0x00]).unwrap();
+
+        let manifest = "spin.bin\n\
+                        mov.bin\n\
+                        ; cx: 0x0003\n\
+                        ; ip: 0x0003\n";
+        let entries = parse_manifest(manifest, dir.path());
+        let outcomes = run_batch(&entries, 2);
+        let limit = &outcomes[0].result;
+        assert!(matches!(limit, Err(IntelError::InstructionLimit(MAX_INSTRUCTIONS))));
+        assert!(outcomes[1].passed());
+    }
+
+    #[test]
+    fn test_catch_panic() {
+        let result: Result<(), IntelError> = catch_panic(|| panic!("at the {}", "disco"));
+        assert!(matches!(result, Err(IntelError::Panicked(message)) if message == "at the disco"));
+
+        assert!(matches!(catch_panic(|| Ok(3)), Ok(3)));
+    }
+}
@@ -0,0 +1,131 @@
+use super::error::*;
+use super::instructions::*;
+use std::collections::BTreeMap;
+
+// Instructions are at most 6 bytes, so a write can only touch instructions starting up to 5 bytes
+// before it.
+const MAX_INSTRUCTION_LEN: usize = 6;
+
+// Decoded instructions keyed by the address they were decoded from. The owner of the memory has to
+// call |invalidate| on every write, so self-modifying code gets decoded again.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct InstructionCache {
+    entries: BTreeMap<usize, Instruction>,
+    pub stats: CacheStats,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub invalidations: usize,
+}
+
+impl InstructionCache {
+    // Returns the instruction at |address|, decoding it from |memory| if it wasn't cached.
+    pub fn fetch(&mut self, memory: &[u8], address: usize) -> Result<&Instruction, IntelError> {
+        if self.entries.contains_key(&address) {
+            self.stats.hits += 1;
+        } else {
+            let bytes = memory
+                .get(address..)
+                .ok_or(IntelError::InvalidAddress(address))?;
+
+            let mut instruction = Instruction::decode(bytes)?;
+            instruction.address = address;
+
+            self.stats.misses += 1;
+            self.entries.insert(address, instruction);
+        }
+
+        Ok(&self.entries[&address])
+    }
+
+    // Drops every cached instruction that overlaps [address, address + len).
+    pub fn invalidate(&mut self, address: usize, len: usize) {
+        let first = address.saturating_sub(MAX_INSTRUCTION_LEN - 1);
+        let stale: Vec<usize> = self
+            .entries
+            .range(first..(address + len))
+            .filter(|(start, instruction)| *start + instruction.len() > address)
+            .map(|(start, _)| *start)
+            .collect();
+
+        for start in stale {
+            self.entries.remove(&start);
+            self.stats.invalidations += 1;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.stats.invalidations += self.entries.len();
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // mov cx, 3
+    // sub cx, 1
+    const PROGRAM: &[u8] = &[0xB9, 0x03, 0x00, 0x83, 0xE9, 0x01];
+
+    #[test]
+    fn test_hits_and_misses() {
+        let mut cache = InstructionCache::default();
+        assert_eq!(cache.fetch(PROGRAM, 0).unwrap().to_string(), "mov cx, 3");
+        assert_eq!(cache.fetch(PROGRAM, 3).unwrap().to_string(), "sub cx, 1");
+        assert_eq!(cache.fetch(PROGRAM, 0).unwrap().address, 0);
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(
+            cache.stats,
+            CacheStats {
+                hits: 1,
+                misses: 2,
+                invalidations: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_invalidate_overlapping() {
+        let mut cache = InstructionCache::default();
+        cache.fetch(PROGRAM, 0).unwrap();
+        cache.fetch(PROGRAM, 3).unwrap();
+
+        // Touching the immediate of the mov only drops the mov.
+        cache.invalidate(2, 1);
+        assert_eq!(cache.len(), 1);
+
+        // A write right after the end of the sub touches nothing.
+        cache.invalidate(6, 2);
+        assert_eq!(cache.len(), 1);
+
+        // A word write across both instructions drops the sub too.
+        cache.fetch(PROGRAM, 0).unwrap();
+        cache.invalidate(2, 2);
+        assert!(cache.is_empty());
+        assert_eq!(cache.stats.invalidations, 3);
+    }
+
+    #[test]
+    fn test_decodes_again_after_write() {
+        let mut memory = PROGRAM.to_vec();
+        let mut cache = InstructionCache::default();
+        assert_eq!(cache.fetch(&memory, 0).unwrap().to_string(), "mov cx, 3");
+
+        memory[1] = 7;
+        cache.invalidate(1, 1);
+        assert_eq!(cache.fetch(&memory, 0).unwrap().to_string(), "mov cx, 7");
+    }
+}
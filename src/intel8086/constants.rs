@@ -0,0 +1,284 @@
+use super::instructions::*;
+use super::registers::*;
+use super::syntax::*;
+use super::traversal::*;
+use std::collections::BTreeMap;
+
+// Constant propagation over the decoded program. Inside every basic block we keep the registers
+// whose value is known (set from an immediate, or computed from other known registers), and use
+// them to resolve the effective address of memory operands. Every block starts with nothing known,
+// so values don't flow across jumps or into loops.
+
+// What we know about the registers at some point of a block. Indexed like the cpu registers.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RegisterValues {
+    values: [Option<u16>; 13],
+}
+
+impl RegisterValues {
+    pub fn get(&self, reg: &Register) -> Option<u16> {
+        if reg.len() == 2 {
+            return self.values[reg.reg as usize];
+        }
+
+        let (word, high) = word_register(reg)?;
+        let value = self.values[word.reg as usize]?;
+        Some(if high { value >> 8 } else { value & 0xFF })
+    }
+
+    pub fn set(&mut self, reg: &Register, value: Option<u16>) {
+        if reg.len() == 2 {
+            self.values[reg.reg as usize] = value;
+            return;
+        }
+
+        // Writing half a register keeps the other half, if we know it.
+        let Some((word, high)) = word_register(reg) else {
+            return;
+        };
+        let index = word.reg as usize;
+        self.values[index] = match (self.values[index], value) {
+            (Some(before), Some(value)) if high => Some((before & 0x00FF) | ((value & 0xFF) << 8)),
+            (Some(before), Some(value)) => Some((before & 0xFF00) | (value & 0xFF)),
+            _ => None,
+        };
+    }
+
+    pub fn clear(&mut self) {
+        self.values = Default::default();
+    }
+
+    pub fn operand(&self, operand: &Operand) -> Option<u16> {
+        match operand {
+            Operand::Immediate(value) => Some(*value),
+            Operand::Register(reg) => self.get(reg),
+            _ => None,
+        }
+    }
+
+    // The offset |eac| refers to, if every register it uses is known. Direct accesses are left
+    // out, there is nothing to resolve.
+    pub fn effective_address(&self, eac: &EAC) -> Option<u16> {
+        let (registers, displacement): (&[Register], u16) = match eac {
+            EAC::BxSi(offset) => (&[REGISTER_BX, REGISTER_SI], *offset),
+            EAC::BxDi(offset) => (&[REGISTER_BX, REGISTER_DI], *offset),
+            EAC::BpSi(offset) => (&[REGISTER_BP, REGISTER_SI], *offset),
+            EAC::BpDi(offset) => (&[REGISTER_BP, REGISTER_DI], *offset),
+            EAC::Si(offset) => (&[REGISTER_SI], *offset),
+            EAC::Di(offset) => (&[REGISTER_DI], *offset),
+            EAC::Bp(offset) => (&[REGISTER_BP], *offset),
+            EAC::Bx(offset) => (&[REGISTER_BX], *offset),
+            EAC::DirectAccess(_) => return None,
+        };
+
+        let mut address = displacement;
+        for reg in registers {
+            address = address.wrapping_add(self.get(reg)?);
+        }
+        Some(address)
+    }
+
+    // Updates the known values with the effect of |instruction|.
+    pub fn apply(&mut self, instruction: &Instruction) {
+        let dst = match &instruction.dst {
+            Operand::Register(reg) => Some(reg),
+            _ => None,
+        };
+
+        match &instruction.operation {
+            Operation::Mov => {
+                if let Some(dst) = dst {
+                    self.set(dst, self.operand(&instruction.src));
+                }
+            }
+            Operation::Add | Operation::Sub => {
+                if let Some(dst) = dst {
+                    let before = self.get(dst);
+                    let src = self.operand(&instruction.src);
+                    let result = match (before, src, &instruction.operation) {
+                        (Some(before), Some(src), Operation::Add) => Some(before.wrapping_add(src)),
+                        (Some(before), Some(src), _) => Some(before.wrapping_sub(src)),
+                        _ => None,
+                    };
+                    self.set(dst, result);
+                }
+            }
+            Operation::Cmp | Operation::Out | Operation::Cli | Operation::Sti => {}
+            Operation::Jump(jump) => {
+                if matches!(jump.jump, Jump::LOOP | Jump::LOOPZ | Jump::LOOPNZ) {
+                    let cx = self.get(&REGISTER_CX).map(|cx| cx.wrapping_sub(1));
+                    self.set(&REGISTER_CX, cx);
+                }
+            }
+            Operation::Div | Operation::Idiv => {
+                self.set(&REGISTER_AX, None);
+                self.set(&REGISTER_DX, None);
+            }
+            Operation::In => {
+                if let Some(dst) = dst {
+                    self.set(dst, None);
+                }
+            }
+            // The handler can change anything.
+            Operation::Int | Operation::Int3 | Operation::Into | Operation::Iret => self.clear(),
+            Operation::Invalid => self.clear(),
+        }
+    }
+}
+
+// Resolves the effective address of every memory operand we can, keyed by instruction address.
+pub fn resolve_addresses(traversal: &Traversal) -> BTreeMap<usize, u16> {
+    let mut addresses = BTreeMap::new();
+
+    for block in &traversal.blocks {
+        let mut values = RegisterValues::default();
+
+        for instruction in &traversal.instructions[block.instructions.clone()] {
+            let eac = match (&instruction.dst, &instruction.src) {
+                (Operand::EAC(eac), _) | (_, Operand::EAC(eac)) => Some(eac),
+                _ => None,
+            };
+            if let Some(address) = eac.and_then(|eac| values.effective_address(eac)) {
+                addresses.insert(instruction.address, address);
+            }
+
+            values.apply(instruction);
+        }
+    }
+
+    addresses
+}
+
+// Prints instructions with |inner|, adding the resolved effective address as a comment.
+//
+// mov cx, [bx + 1000] ; ea = 2000
+pub struct AddressAnnotations<'a> {
+    pub inner: &'a dyn SyntaxFormatter,
+    pub addresses: BTreeMap<usize, u16>,
+}
+
+impl<'a> AddressAnnotations<'a> {
+    pub fn new(inner: &'a dyn SyntaxFormatter, traversal: &Traversal) -> Self {
+        AddressAnnotations {
+            inner,
+            addresses: resolve_addresses(traversal),
+        }
+    }
+}
+
+impl SyntaxFormatter for AddressAnnotations<'_> {
+    fn header(&self) -> &'static str {
+        self.inner.header()
+    }
+
+    fn instruction(&self, instruction: &Instruction) -> String {
+        let asm = self.inner.instruction(instruction);
+        match self.addresses.get(&instruction.address) {
+            Some(address) => format!("{} {} ea = {}", asm, self.comment(), address),
+            None => asm,
+        }
+    }
+
+    fn jump_to_label(&self, instruction: &Instruction, label: &str) -> String {
+        self.inner.jump_to_label(instruction, label)
+    }
+
+    fn label(&self, name: &str) -> String {
+        self.inner.label(name)
+    }
+
+    fn data(&self, bytes: &[u8]) -> String {
+        self.inner.data(bytes)
+    }
+
+    fn comment(&self) -> &'static str {
+        self.inner.comment()
+    }
+}
+
+// HELPERS -----------------------------------------------------------------------------------------
+
+// The word register holding a byte register, and whether it is the high half.
+fn word_register(reg: &Register) -> Option<(Register, bool)> {
+    let word = Register::find(&format!("{}x", reg.name.get(..1)?))?;
+    Some((word, reg.name.ends_with('h')))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_addresses() {
+        // mov bx, 1000
+        // mov cx, [bx + 1000]
+        // add bx, 2
+        // mov [bx], cx
+        // mov bx, [si]
+        // mov [bx + si], cx
+        #[rustfmt::skip]
+        let bytes = [
+            0xBB, 0xE8, 0x03,
+            0x8B, 0x8F, 0xE8, 0x03,
+            0x83, 0xC3, 0x02,
+            0x89, 0x0F,
+            0x8B, 0x1C,
+            0x89, 0x08,
+        ];
+        let traversal = disassemble_recursive(&bytes, &[0]);
+
+        let addresses = resolve_addresses(&traversal);
+        assert_eq!(addresses, BTreeMap::from([(3, 2000), (10, 1002)]));
+
+        let formatter = AddressAnnotations::new(&Nasm, &traversal);
+        assert_eq!(
+            to_asm_with_syntax(&traversal.instructions, &formatter),
+            "bits 16\n\n\
+             mov bx, 1000\n\
+             mov cx, [bx + 1000] ; ea = 2000\n\
+             add bx, 2\n\
+             mov [bx + 0], cx ; ea = 1002\n\
+             mov bx, [si + 0]\n\
+             mov [bx + si + 0], cx\n"
+        );
+    }
+
+    #[test]
+    fn test_annotations_use_the_syntax_comment() {
+        // mov bx, 1000
+        // mov cx, [bx + 1000]
+        let bytes = [0xBB, 0xE8, 0x03, 0x8B, 0x8F, 0xE8, 0x03];
+        let traversal = disassemble_recursive(&bytes, &[0]);
+
+        let formatter = AddressAnnotations::new(&Att, &traversal);
+        assert_eq!(
+            formatter.instruction(&traversal.instructions[1]),
+            "mov 0x3e8(%bx),%cx # ea = 2000"
+        );
+    }
+
+    #[test]
+    fn test_byte_registers() {
+        let mut values = RegisterValues::default();
+        values.set(&REGISTER_BX, Some(0x1234));
+        values.set(&REGISTER_BL, Some(0));
+        assert_eq!(values.get(&REGISTER_BX), Some(0x1200));
+        assert_eq!(values.get(&REGISTER_BH), Some(0x12));
+
+        // Half of an unknown register doesn't make it known.
+        values.set(&REGISTER_AH, Some(1));
+        assert_eq!(values.get(&REGISTER_AX), None);
+    }
+
+    #[test]
+    fn test_values_stay_in_their_block() {
+        // mov bx, 10
+        // top:
+        // mov [bx], cx
+        // jmp top
+        let bytes = [0xBB, 0x0A, 0x00, 0x89, 0x0F, 0xEB, 0xFC];
+        let traversal = disassemble_recursive(&bytes, &[0]);
+
+        assert!(resolve_addresses(&traversal).is_empty());
+    }
+}
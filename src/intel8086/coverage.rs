@@ -0,0 +1,222 @@
+use super::instructions::*;
+use super::listing::{format_line, MAX_BYTES_PER_LINE};
+use super::syntax::*;
+use super::traversal::*;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+// How many times the instruction at each address was executed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    pub hits: BTreeMap<usize, usize>,
+}
+
+impl Coverage {
+    // Executed instructions have physical addresses. The hits are kept by offset from |start|,
+    // where the program was loaded.
+    pub fn from_executed(executed: &[Instruction], start: usize) -> Self {
+        let mut coverage = Coverage::default();
+        for instruction in executed {
+            if let Some(offset) = instruction.address.checked_sub(start) {
+                coverage.record(offset);
+            }
+        }
+        coverage
+    }
+
+    pub fn record(&mut self, address: usize) {
+        *self.hits.entry(address).or_default() += 1;
+    }
+
+    pub fn hits(&self, address: usize) -> usize {
+        self.hits.get(&address).copied().unwrap_or(0)
+    }
+}
+
+// A run of instructions that never executed.
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnexecutedRange {
+    pub range: Range<usize>,
+    pub instructions: usize,
+}
+
+// Coverage of a program image. The image is decoded from every executed address (and its entry
+// point), so code that is only reached through a jump we never took still shows up. What cannot be
+// reached at all is kept as data and doesn't count.
+pub struct CoverageReport {
+    pub traversal: Traversal,
+    pub coverage: Coverage,
+}
+
+impl CoverageReport {
+    pub fn new(program: &[u8], coverage: Coverage) -> Self {
+        let mut entry_points: Vec<usize> = coverage.hits.keys().copied().collect();
+        entry_points.push(0);
+
+        CoverageReport {
+            traversal: disassemble_recursive(program, &entry_points),
+            coverage,
+        }
+    }
+
+    pub fn executed(&self) -> usize {
+        self.traversal
+            .instructions
+            .iter()
+            .filter(|i| self.coverage.hits(i.address) > 0)
+            .count()
+    }
+
+    pub fn unexecuted(&self) -> Vec<UnexecutedRange> {
+        let mut ranges: Vec<UnexecutedRange> = vec![];
+
+        for instruction in &self.traversal.instructions {
+            if self.coverage.hits(instruction.address) > 0 {
+                continue;
+            }
+
+            let end = instruction.address + instruction.len();
+            match ranges.last_mut() {
+                Some(last) if last.range.end == instruction.address => {
+                    last.range.end = end;
+                    last.instructions += 1;
+                }
+                _ => ranges.push(UnexecutedRange {
+                    range: instruction.address..end,
+                    instructions: 1,
+                }),
+            }
+        }
+
+        ranges
+    }
+
+    // The listing of |to_listing| for a program loaded at |segment|, with the hit count of every
+    // instruction in front.
+    //
+    //      3  0000:0003  83 E9 01            sub cx, 1
+    //      -  0000:0008  BB 05 00            mov bx, 5
+    pub fn to_listing(&self, segment: u16, formatter: &dyn SyntaxFormatter) -> String {
+        let mut lines: Vec<(usize, String)> = vec![];
+
+        for instruction in &self.traversal.instructions {
+            let hits = match self.coverage.hits(instruction.address) {
+                0 => "-".to_string(),
+                hits => hits.to_string(),
+            };
+            let line = format_line(
+                segment,
+                instruction.address,
+                &instruction.data[..instruction.len()],
+                &formatter.instruction(instruction),
+            );
+            lines.push((instruction.address, format!("{:>6}  {}", hits, line)));
+        }
+
+        for block in &self.traversal.data {
+            for (i, chunk) in block.bytes.chunks(MAX_BYTES_PER_LINE).enumerate() {
+                let address = block.address + i * MAX_BYTES_PER_LINE;
+                let line = format_line(segment, address, chunk, &formatter.data(chunk));
+                lines.push((address, format!("{:>6}  {}", "", line)));
+            }
+        }
+
+        lines.sort_by_key(|(address, _)| *address);
+
+        let mut string = String::new();
+        for (_, line) in lines {
+            string.push_str(line.trim_end());
+            string.push('\n');
+        }
+        string
+    }
+}
+
+impl std::fmt::Display for CoverageReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let total = self.traversal.instructions.len();
+        let executed = self.executed();
+        let percent = (executed * 100).checked_div(total).unwrap_or(100);
+        writeln!(f, "{} of {} instructions executed ({}%)", executed, total, percent)?;
+
+        for unexecuted in self.unexecuted() {
+            writeln!(
+                f,
+                "never executed: {:04X}..{:04X} ({} instructions)",
+                unexecuted.range.start, unexecuted.range.end, unexecuted.instructions
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intel8086::simulate;
+
+    #[test]
+    fn test_branch_not_taken() {
+        // mov cx, 1
+        // sub cx, 1
+        // je skip
+        // mov bx, 5
+        // mov bx, 6
+        // skip:
+        // mov dx, 2
+        let program = [
+            0xB9, 0x01, 0x00, 0x83, 0xE9, 0x01, 0x74, 0x06, 0xBB, 0x05, 0x00, 0xBB, 0x06, 0x00,
+            0xBA, 0x02, 0x00,
+        ];
+        let result = simulate(&program).unwrap();
+        let report = CoverageReport::new(
+            &program,
+            Coverage::from_executed(&result.executed_instructions, 0),
+        );
+
+        assert_eq!(report.executed(), 4);
+        assert_eq!(
+            report.unexecuted(),
+            vec![UnexecutedRange {
+                range: 8..14,
+                instructions: 2,
+            }]
+        );
+        assert_eq!(
+            report.to_string(),
+            "4 of 6 instructions executed (66%)\nnever executed: 0008..000E (2 instructions)\n"
+        );
+
+        let listing = report.to_listing(0, &Nasm);
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines[2], "     1  0000:0006  74 06               je $+8+0");
+        assert_eq!(lines[3], "     -  0000:0008  BB 05 00            mov bx, 5");
+    }
+
+    #[test]
+    fn test_loop_hits() {
+        // mov cx, 3
+        // top:
+        // sub cx, 1
+        // jnz top
+        // db 0xF4
+        let program = [0xB9, 0x03, 0x00, 0x83, 0xE9, 0x01, 0x75, 0xFB, 0xF4];
+        let mut coverage = Coverage::default();
+        coverage.record(0);
+        for _ in 0..3 {
+            coverage.record(3);
+            coverage.record(6);
+        }
+
+        let report = CoverageReport::new(&program, coverage);
+        assert!(report.unexecuted().is_empty());
+        assert_eq!(
+            report.to_listing(0, &Nasm),
+            "     1  0000:0000  B9 03 00            mov cx, 3\n     \
+             3  0000:0003  83 E9 01            sub cx, 1\n     \
+             3  0000:0006  75 FB               jne $-3+0\n        \
+             0000:0008  F4                  db 0xF4\n"
+        );
+    }
+}
@@ -1,22 +1,87 @@
+use super::cache::*;
 use super::error::*;
 use super::instructions::*;
+use super::io::*;
 use super::registers::*;
 use super::tables::*;
 use log::*;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
 
-#[derive(Default, Eq)]
+#[derive(Default)]
 pub struct CPU {
-    registers: [u16; 9],
+    // The general registers, ip, and the segment registers, indexed by Register::reg.
+    registers: [u16; 13],
     memory: Vec<u8>,
     pub flags: CPUFlags,
+    decoded: InstructionCache,
+    // Devices behind the in/out instructions.
+    pub io: IoBus,
+    // Instructions simulated so far. Scheduled interrupts count against it.
+    executed: usize,
+    // External interrupts as (instruction count, vector), sorted by instruction count.
+    scheduled: Vec<(usize, u8)>,
+    // Set by sti. External interrupts wait until the instruction after it has run too.
+    interrupts_inhibited: bool,
 }
 
-#[derive(Debug, Default, Eq, PartialEq)]
+#[derive(Debug, Default, Eq, PartialEq, serde::Serialize)]
 pub struct CPUFlags {
     pub z: bool,
     pub s: bool,
+    pub o: bool,
+    // Interrupt enable and trap (single step).
+    pub i: bool,
+    pub t: bool,
 }
 
+// Positions in the FLAGS register, as pushed on the stack.
+const FLAG_Z: u16 = 1 << 6;
+const FLAG_S: u16 = 1 << 7;
+const FLAG_T: u16 = 1 << 8;
+const FLAG_I: u16 = 1 << 9;
+const FLAG_O: u16 = 1 << 11;
+// Bits 12-15 and 1 always read as set on an 8086.
+const FLAGS_RESERVED: u16 = 0xF002;
+
+impl CPUFlags {
+    pub fn to_word(&self) -> u16 {
+        let mut word = FLAGS_RESERVED;
+        for (set, bit) in [
+            (self.z, FLAG_Z),
+            (self.s, FLAG_S),
+            (self.o, FLAG_O),
+            (self.i, FLAG_I),
+            (self.t, FLAG_T),
+        ] {
+            if set {
+                word |= bit;
+            }
+        }
+        word
+    }
+
+    pub fn from_word(word: u16) -> Self {
+        CPUFlags {
+            z: word & FLAG_Z != 0,
+            s: word & FLAG_S != 0,
+            o: word & FLAG_O != 0,
+            i: word & FLAG_I != 0,
+            t: word & FLAG_T != 0,
+        }
+    }
+}
+
+// Interrupt vectors the CPU raises by itself.
+pub const DIVIDE_ERROR: u8 = 0;
+pub const SINGLE_STEP: u8 = 1;
+pub const BREAKPOINT: u8 = 3;
+pub const OVERFLOW: u8 = 4;
+
+// Cycles to enter a handler that no instruction asked for.
+const EXTERNAL_INTERRUPT_CYCLES: usize = 61;
+const SINGLE_STEP_CYCLES: usize = 50;
+const DIVIDE_ERROR_CYCLES: usize = 51;
+
 impl CPU {
     pub fn new() -> Self {
         CPU {
@@ -61,8 +126,24 @@ impl CPU {
         self.registers[8]
     }
 
+    pub fn es(&self) -> u16 {
+        self.get_register(&REGISTER_ES)
+    }
+
+    pub fn cs(&self) -> u16 {
+        self.get_register(&REGISTER_CS)
+    }
+
+    pub fn ss(&self) -> u16 {
+        self.get_register(&REGISTER_SS)
+    }
+
+    pub fn ds(&self) -> u16 {
+        self.get_register(&REGISTER_DS)
+    }
+
     pub fn ip_address(&self) -> usize {
-        self.ip() as usize
+        physical_address(self.cs(), self.ip())
     }
 
     fn set_ip(&mut self, ip: u16) {
@@ -83,39 +164,103 @@ impl CPU {
         }
 
         self.memory[..program.len()].copy_from_slice(program);
+        self.decoded.clear();
         Ok(())
     }
 
+    // Loads |program| at |segment|:0 and starts executing it from there. Programs loaded at 0
+    // overlap the interrupt vector table.
+    pub fn set_program_at(&mut self, segment: u16, program: &[u8]) -> Result<(), IntelError> {
+        let start = physical_address(segment, 0);
+        if start + program.len() > self.memory.len() {
+            return Err(IntelError::ProgramTooBig(start + program.len(), self.memory.len()));
+        }
+
+        self.memory[start..start + program.len()].copy_from_slice(program);
+        self.decoded.clear();
+        self.set_register(&REGISTER_CS, segment);
+        self.set_ip(0);
+        Ok(())
+    }
+
+    // The handler for |vector| as (segment, offset), from the table at address 0.
+    pub fn interrupt_vector(&self, vector: u8) -> (u16, u16) {
+        let address = vector as usize * 4;
+        (self.loadu16(address + 2), self.loadu16(address))
+    }
+
+    pub fn set_interrupt_vector(&mut self, vector: u8, segment: u16, offset: u16) {
+        let address = vector as usize * 4;
+        self.storeu16(address, offset);
+        self.storeu16(address + 2, segment);
+    }
+
+    // Raises |vector| as an external interrupt once |instruction_count| instructions have run.
+    // Like a real INTR it waits for IF if interrupts are disabled by then.
+    pub fn raise_interrupt_at(&mut self, instruction_count: usize, vector: u8) {
+        let index = self
+            .scheduled
+            .partition_point(|(count, _)| *count <= instruction_count);
+        self.scheduled.insert(index, (instruction_count, vector));
+    }
+
+    pub fn executed_instructions(&self) -> usize {
+        self.executed
+    }
+
     pub fn get_memory(&self) -> &[u8] {
         &self.memory
     }
 
+    // Decodes the instruction at |address|. Instructions are cached until memory under them is
+    // written, so loops are decoded only once.
+    pub fn fetch(&mut self, address: usize) -> Result<Instruction, IntelError> {
+        self.decoded.fetch(&self.memory, address).cloned()
+    }
+
+    pub fn cache_stats(&self) -> &CacheStats {
+        &self.decoded.stats
+    }
+
     pub fn simulate(&mut self, instruction: &Instruction) -> Result<usize, IntelError> {
+        // TF is looked at before the instruction runs, so the instruction that sets it doesn't
+        // trap.
+        let trap = self.flags.t;
+
         // Update the IP immediatelly.
         self.set_ip(self.ip() + instruction.len as u16);
 
-        match &instruction.operation {
-            Operation::Mov => {
-                return self.simulate_mov(instruction);
-            }
-            Operation::Add => {
-                return self.simulate_op(instruction);
-            }
-            Operation::Sub => {
-                return self.simulate_op(instruction);
-            }
-            Operation::Cmp => {
-                return self.simulate_op(instruction);
-            }
+        let cycles = match &instruction.operation {
+            Operation::Mov => self.simulate_mov(instruction)?,
+            Operation::Add => self.simulate_op(instruction)?,
+            Operation::Sub => self.simulate_op(instruction)?,
+            Operation::Cmp => self.simulate_op(instruction)?,
             Operation::Jump(jump_description) => {
-                return self.simulate_jump(instruction, &jump_description);
+                self.simulate_jump(instruction, jump_description)?
             }
+            Operation::In | Operation::Out => self.simulate_in_out(instruction)?,
+            Operation::Div | Operation::Idiv => self.simulate_division(instruction)?,
+            Operation::Int
+            | Operation::Int3
+            | Operation::Into
+            | Operation::Iret
+            | Operation::Cli
+            | Operation::Sti => self.simulate_interrupt_instruction(instruction)?,
             _ => {
                 return Err(IntelError::UnsupportedSimulationOperation(
                     instruction.operation.to_string(),
                 ));
             }
-        }
+        };
+        self.executed += 1;
+
+        // The devices run on the same clock as the CPU.
+        self.io.tick(cycles);
+
+        let interrupt_cycles = self.service_interrupts(trap);
+        self.io.tick(interrupt_cycles);
+
+        Ok(cycles + interrupt_cycles)
     }
 
     fn simulate_mov(&mut self, instruction: &Instruction) -> Result<usize, IntelError> {
@@ -155,8 +300,8 @@ impl CPU {
             Operand::EAC(eac) => {
                 let (address, before) = self.resolve_eac(&eac);
 
-                self.storeu16(address as usize, src);
-                let dst_str = format!("address: {}", printu16(address));
+                self.storeu16(address, src);
+                let dst_str = format!("address: 0x{:05X}", address);
                 (before, dst_str, src)
             }
             _ => {
@@ -214,7 +359,7 @@ impl CPU {
 
                 let result = match &instruction.operation {
                     Operation::Add => {
-                        let result: u16 = before + src;
+                        let result: u16 = before.wrapping_add(src);
                         self.set_register(&dst, result);
                         self.process_flags(result as i32);
                         result
@@ -241,18 +386,18 @@ impl CPU {
             }
             Operand::EAC(eac) => {
                 let (address, before) = self.resolve_eac(&eac);
-                let dst_str = format!("address: {}", printu16(address));
+                let dst_str = format!("address: 0x{:05X}", address);
 
                 let result = match &instruction.operation {
                     Operation::Add => {
-                        let result: u16 = before + src;
-                        self.storeu16(address as usize, result);
+                        let result: u16 = before.wrapping_add(src);
+                        self.storeu16(address, result);
                         self.process_flags(result as i32);
                         result
                     }
                     Operation::Sub => {
                         let result: i32 = (before as i32) - (src as i32);
-                        self.storeu16(address as usize, result as u16);
+                        self.storeu16(address, result as u16);
                         self.process_flags(result);
                         result as u16
                     }
@@ -276,6 +421,7 @@ impl CPU {
                 return Err(IntelError::InvalidOperand(msg));
             }
         };
+        self.flags.o = overflows(&instruction.operation, before, src);
 
         let mut cycles = 0xFFFFFFF;
         let mut cycles_explanation: String = "".to_string();
@@ -298,6 +444,209 @@ impl CPU {
         Ok(cycles)
     }
 
+    fn simulate_in_out(&mut self, instruction: &Instruction) -> Result<usize, IntelError> {
+        let (accum, port) = match instruction.operation {
+            Operation::In => (&instruction.dst, &instruction.src),
+            _ => (&instruction.src, &instruction.dst),
+        };
+
+        let Operand::Register(accum) = accum else {
+            return Err(IntelError::InvalidOperand(accum.to_string()));
+        };
+        let port = match port {
+            Operand::Immediate(port) => *port,
+            Operand::Register(reg) if *reg == REGISTER_DX => self.dx(),
+            _ => return Err(IntelError::InvalidOperand(port.to_string())),
+        };
+        let wide = accum.len() == 2;
+
+        // A word goes through two consecutive ports, low byte first.
+        let before = self.ax();
+        if let Operation::In = instruction.operation {
+            let low = self.io.read(port) as u16;
+            let value = if wide {
+                low | ((self.io.read(port.wrapping_add(1)) as u16) << 8)
+            } else {
+                (before & 0xFF00) | low
+            };
+            self.set_register(&REGISTER_AX, value);
+        } else {
+            self.io.write(port, before as u8);
+            if wide {
+                self.io.write(port.wrapping_add(1), (before >> 8) as u8);
+            }
+        }
+
+        let (cycles, cycles_explanation) = self.determine_instruction_cycle_cost(instruction)?;
+
+        info!(
+            "\"{0}\" port: 0x{1:04X}, {2} -> {3} (cycles: {4}, ({5}))",
+            right_pad(instruction, ' ', PAD_AMOUNT),
+            port,
+            printu16(before),
+            printu16(self.ax()),
+            cycles,
+            cycles_explanation,
+        );
+
+        Ok(cycles)
+    }
+
+    // div/idiv with a word divisor: dx:ax / divisor, quotient in ax and remainder in dx.
+    fn simulate_division(&mut self, instruction: &Instruction) -> Result<usize, IntelError> {
+        let divisor = match &instruction.dst {
+            // For now we support only big registers.
+            Operand::Register(reg) if reg.len() == 2 => self.get_register(reg),
+            Operand::EAC(eac) if instruction.bits.w() => self.resolve_eac(eac).1,
+            _ => return Err(IntelError::InvalidOperand(instruction.dst.to_string())),
+        };
+        let dividend = ((self.dx() as u32) << 16) | self.ax() as u32;
+
+        let (mut cycles, cycles_explanation) =
+            self.determine_instruction_cycle_cost(instruction)?;
+
+        match divide(&instruction.operation, dividend, divisor) {
+            Some((quotient, remainder)) => {
+                self.set_register(&REGISTER_AX, quotient);
+                self.set_register(&REGISTER_DX, remainder);
+            }
+            None => {
+                // On the 8086 the pushed IP points after the div, not at it.
+                info!("divide error: 0x{:08X} / {}", dividend, printu16(divisor));
+                self.interrupt(DIVIDE_ERROR);
+                cycles += DIVIDE_ERROR_CYCLES;
+            }
+        }
+
+        info!(
+            "\"{0}\" dividend: 0x{1:08X}, ax: {2}, dx: {3} (cycles: {4}, ({5}))",
+            right_pad(instruction, ' ', PAD_AMOUNT),
+            dividend,
+            printu16(self.ax()),
+            printu16(self.dx()),
+            cycles,
+            cycles_explanation,
+        );
+
+        Ok(cycles)
+    }
+
+    fn simulate_interrupt_instruction(
+        &mut self,
+        instruction: &Instruction,
+    ) -> Result<usize, IntelError> {
+        let before = (self.cs(), self.ip());
+        let (mut cycles, cycles_explanation) =
+            self.determine_instruction_cycle_cost(instruction)?;
+
+        match &instruction.operation {
+            Operation::Int => {
+                let Operand::Immediate(vector) = instruction.dst else {
+                    return Err(IntelError::InvalidOperand(instruction.dst.to_string()));
+                };
+                self.interrupt(vector as u8);
+            }
+            Operation::Int3 => self.interrupt(BREAKPOINT),
+            Operation::Into => {
+                if self.flags.o {
+                    self.interrupt(OVERFLOW);
+                    cycles += 49;
+                }
+            }
+            Operation::Iret => {
+                let ip = self.pop();
+                let cs = self.pop();
+                self.set_register(&REGISTER_CS, cs);
+                self.set_ip(ip);
+                self.flags = CPUFlags::from_word(self.pop());
+            }
+            Operation::Cli => self.flags.i = false,
+            Operation::Sti => {
+                self.flags.i = true;
+                self.interrupts_inhibited = true;
+            }
+            _ => {
+                return Err(IntelError::UnsupportedSimulationOperation(
+                    instruction.operation.to_string(),
+                ));
+            }
+        }
+
+        info!(
+            "\"{0}\" cs:ip: {1:04X}:{2:04X}->{3:04X}:{4:04X} - flags: {5} (cycles: {6} ({7}))",
+            right_pad(instruction, ' ', PAD_AMOUNT),
+            before.0,
+            before.1,
+            self.cs(),
+            self.ip(),
+            self.print_flags(),
+            cycles,
+            cycles_explanation,
+        );
+
+        Ok(cycles)
+    }
+
+    // Runs between instructions. An external interrupt goes first if IF allows it (and we are not
+    // right after sti), otherwise the single-step trap if TF was set. Returns the cycles spent
+    // entering the handler.
+    fn service_interrupts(&mut self, trap: bool) -> usize {
+        let inhibited = std::mem::take(&mut self.interrupts_inhibited);
+        if self.flags.i && !inhibited {
+            if let Some(vector) = self.next_external_interrupt() {
+                info!("external interrupt {}", vector);
+                self.interrupt(vector);
+                return EXTERNAL_INTERRUPT_CYCLES;
+            }
+        }
+
+        // Entering a handler clears TF, so int doesn't trap into its own handler.
+        if trap && self.flags.t {
+            info!("single step trap");
+            self.interrupt(SINGLE_STEP);
+            return SINGLE_STEP_CYCLES;
+        }
+
+        0
+    }
+
+    // Scheduled interrupts go before the ones coming from the interrupt controller.
+    fn next_external_interrupt(&mut self) -> Option<u8> {
+        match self.scheduled.first() {
+            Some((count, vector)) if *count <= self.executed => {
+                let vector = *vector;
+                self.scheduled.remove(0);
+                Some(vector)
+            }
+            _ => self.io.acknowledge_interrupt(),
+        }
+    }
+
+    // Pushes FLAGS, CS and IP, clears IF and TF and jumps to the handler in the vector table.
+    fn interrupt(&mut self, vector: u8) {
+        self.push(self.flags.to_word());
+        self.push(self.cs());
+        self.push(self.ip());
+
+        self.flags.i = false;
+        self.flags.t = false;
+
+        let (segment, offset) = self.interrupt_vector(vector);
+        self.set_register(&REGISTER_CS, segment);
+        self.set_ip(offset);
+    }
+
+    fn push(&mut self, value: u16) {
+        self.set_register(&REGISTER_SP, self.sp().wrapping_sub(2));
+        self.storeu16(physical_address(self.ss(), self.sp()), value);
+    }
+
+    fn pop(&mut self) -> u16 {
+        let value = self.loadu16(physical_address(self.ss(), self.sp()));
+        self.set_register(&REGISTER_SP, self.sp().wrapping_add(2));
+        value
+    }
+
     fn simulate_jump(
         &mut self,
         instruction: &Instruction,
@@ -315,72 +664,49 @@ impl CPU {
             ));
         }
 
-        match jump_description.jump {
-            Jump::JO => todo!(),
-            Jump::JNO => todo!(),
-            Jump::JB => todo!(),
-            Jump::JNB => todo!(),
-            Jump::JE => {
-                if self.flags.z {
-                    self.set_ip(src)
-                }
-            }
-            Jump::JNE => {
-                if !self.flags.z {
-                    self.set_ip(src)
-                }
-            }
-            Jump::JBE => todo!(),
-            Jump::JNBE => todo!(),
-            Jump::JS => {
-                if self.flags.s {
-                    self.set_ip(src)
-                }
-            }
-            Jump::JNS => {
-                if !self.flags.s {
-                    self.set_ip(src)
-                }
-            }
-            Jump::JP => todo!(),
-            Jump::JNP => todo!(),
-            Jump::JL => todo!(),
-            Jump::JNL => todo!(),
-            Jump::JLE => todo!(),
-            Jump::JNLE => todo!(),
-            Jump::JCXZ => todo!(),
-            Jump::JMP => todo!(),
-            Jump::LOOPNZ => {
-                let cx = self.cx() - 1;
-                self.set_register(&REGISTER_CX, cx);
-                if cx == 0 && !self.flags.z {
-                    self.set_ip(src)
-                }
-            }
-            Jump::LOOPZ => {
-                let cx = self.cx() - 1;
-                self.set_register(&REGISTER_CX, cx);
-                if cx == 0 && self.flags.z {
-                    self.set_ip(src)
-                }
+        let flags = &self.flags;
+        let taken = match jump_description.jump {
+            Jump::JO => flags.o,
+            Jump::JNO => !flags.o,
+            Jump::JE => flags.z,
+            Jump::JNE => !flags.z,
+            Jump::JS => flags.s,
+            Jump::JNS => !flags.s,
+            Jump::JL => flags.s != flags.o,
+            Jump::JNL => flags.s == flags.o,
+            Jump::JLE => flags.z || flags.s != flags.o,
+            Jump::JNLE => !flags.z && flags.s == flags.o,
+            // These look at the carry and parity flags, which we don't keep.
+            Jump::JB | Jump::JNB | Jump::JBE | Jump::JNBE | Jump::JP | Jump::JNP => {
+                return Err(IntelError::UnsupportedSimulationOperation(
+                    instruction.operation.to_string(),
+                ));
             }
-            Jump::LOOP => {
-                let cx = self.cx() - 1;
+            Jump::JCXZ => self.cx() == 0,
+            Jump::JMP => true,
+            Jump::LOOP | Jump::LOOPZ | Jump::LOOPNZ => {
+                let cx = self.cx().wrapping_sub(1);
                 self.set_register(&REGISTER_CX, cx);
-                if cx == 0 && self.flags.z {
-                    self.set_ip(src)
+                match jump_description.jump {
+                    Jump::LOOPZ => cx != 0 && self.flags.z,
+                    Jump::LOOPNZ => cx != 0 && !self.flags.z,
+                    _ => cx != 0,
                 }
             }
+        };
+
+        if taken {
+            self.set_ip(src);
         }
 
         let after = self.ip();
 
-        let mut cycles = 0xFFFFFFF;
-        let mut cycles_explanation: String = "".to_string();
-        if let Ok((c, e)) = self.determine_instruction_cycle_cost(instruction) {
-            cycles = c;
-            cycles_explanation = e;
-        }
+        let (taken_cycles, not_taken_cycles) = jump_cycles(&jump_description.jump);
+        let cycles = if taken {
+            taken_cycles
+        } else {
+            not_taken_cycles
+        };
 
         info!(
             "\"{0}\" ip: 0x{1:04X}->0x{2:04X} ({1} -> {2}) (cycles: {3} ({4}))",
@@ -388,7 +714,7 @@ impl CPU {
             before,
             after,
             cycles,
-            cycles_explanation,
+            if taken { "taken" } else { "not taken" },
         );
 
         Ok(cycles)
@@ -399,7 +725,7 @@ impl CPU {
         self.flags.s = value < 0;
     }
 
-    fn print_flags(&self) -> String {
+    pub fn print_flags(&self) -> String {
         let mut result = String::new();
         if self.flags.z {
             result.push('Z')
@@ -407,25 +733,47 @@ impl CPU {
         if self.flags.s {
             result.push('S')
         }
+        if self.flags.t {
+            result.push('T')
+        }
+        if self.flags.i {
+            result.push('I')
+        }
+        if self.flags.o {
+            result.push('O')
+        }
         result
     }
 
-    // Returns the resolved address and value.
-    // In the case of DirectAccess, the address is 0.
-    fn resolve_eac(&self, eac: &EAC) -> (u16, u16) {
-        let address = match eac {
-            EAC::BxSi(offset) => self.bx() + self.si() + offset,
-            EAC::BxDi(offset) => self.bx() + self.di() + offset,
-            EAC::BpSi(offset) => self.bp() + self.si() + offset,
-            EAC::BpDi(offset) => self.bp() + self.di() + offset,
-            EAC::Si(offset) => self.si() + offset,
-            EAC::Di(offset) => self.di() + offset,
-            EAC::Bp(offset) => self.bp() + offset,
-            EAC::Bx(offset) => self.bx() + offset,
-            EAC::DirectAccess(address) => *address,
+    // Returns the resolved physical address and value. Addresses based on bp are relative to ss,
+    // the rest to ds.
+    fn resolve_eac(&self, eac: &EAC) -> (usize, u16) {
+        let (segment, offset) = match eac {
+            EAC::BxSi(offset) => {
+                let base = self.bx().wrapping_add(self.si());
+                (self.ds(), base.wrapping_add(*offset))
+            }
+            EAC::BxDi(offset) => {
+                let base = self.bx().wrapping_add(self.di());
+                (self.ds(), base.wrapping_add(*offset))
+            }
+            EAC::BpSi(offset) => {
+                let base = self.bp().wrapping_add(self.si());
+                (self.ss(), base.wrapping_add(*offset))
+            }
+            EAC::BpDi(offset) => {
+                let base = self.bp().wrapping_add(self.di());
+                (self.ss(), base.wrapping_add(*offset))
+            }
+            EAC::Si(offset) => (self.ds(), self.si().wrapping_add(*offset)),
+            EAC::Di(offset) => (self.ds(), self.di().wrapping_add(*offset)),
+            EAC::Bp(offset) => (self.ss(), self.bp().wrapping_add(*offset)),
+            EAC::Bx(offset) => (self.ds(), self.bx().wrapping_add(*offset)),
+            EAC::DirectAccess(address) => (self.ds(), *address),
         };
 
-        (address, self.loadu16(address as usize))
+        let address = physical_address(segment, offset);
+        (address, self.loadu16(address))
     }
 
     fn determine_instruction_cycle_cost(
@@ -492,9 +840,12 @@ impl CPU {
         let b2: u8 = (value >> 8) as u8;
         self.memory[address] = b1;
         self.memory[address + 1] = b2;
+        self.decoded.invalidate(address, 2);
     }
 }
 
+impl Eq for CPU {}
+
 impl PartialEq for CPU {
     fn eq(&self, other: &Self) -> bool {
         if self.registers != other.registers {
@@ -505,11 +856,33 @@ impl PartialEq for CPU {
             return false;
         }
 
-        // We don't compare memory.
+        // We don't compare memory nor devices.
         return true;
     }
 }
 
+// Memory is left out, it can be dumped separately with --dump_memory.
+impl Serialize for CPU {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("CPU", 14)?;
+        state.serialize_field("ax", &self.ax())?;
+        state.serialize_field("cx", &self.cx())?;
+        state.serialize_field("dx", &self.dx())?;
+        state.serialize_field("bx", &self.bx())?;
+        state.serialize_field("sp", &self.sp())?;
+        state.serialize_field("bp", &self.bp())?;
+        state.serialize_field("si", &self.si())?;
+        state.serialize_field("di", &self.di())?;
+        state.serialize_field("ip", &self.ip())?;
+        state.serialize_field("es", &self.es())?;
+        state.serialize_field("cs", &self.cs())?;
+        state.serialize_field("ss", &self.ss())?;
+        state.serialize_field("ds", &self.ds())?;
+        state.serialize_field("flags", &self.flags)?;
+        state.end()
+    }
+}
+
 impl std::fmt::Debug for CPU {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("CPU")
@@ -522,6 +895,10 @@ impl std::fmt::Debug for CPU {
             .field("si", &printu16(self.si()))
             .field("di", &printu16(self.di()))
             .field("ip", &printu16(self.ip()))
+            .field("es", &printu16(self.es()))
+            .field("cs", &printu16(self.cs()))
+            .field("ss", &printu16(self.ss()))
+            .field("ds", &printu16(self.ds()))
             .field("flags", &self.print_flags())
             .finish()
     }
@@ -540,8 +917,314 @@ fn right_pad(value: impl std::fmt::Display, pad: char, width: usize) -> String {
         .collect::<String>()
 }
 
-fn is_odd(value: u16) -> bool {
+// segment:offset -> physical address, wrapping around at 1MB like the 8086.
+pub fn physical_address(segment: u16, offset: u16) -> usize {
+    (((segment as usize) << 4) + offset as usize) & 0xFFFFF
+}
+
+// Whether the signed result of add/sub/cmp doesn't fit in 16 bits.
+fn overflows(operation: &Operation, a: u16, b: u16) -> bool {
+    let overflow = match operation {
+        Operation::Add => {
+            let result = a.wrapping_add(b);
+            (a ^ result) & (b ^ result)
+        }
+        _ => {
+            let result = a.wrapping_sub(b);
+            (a ^ b) & (a ^ result)
+        }
+    };
+    overflow & 0x8000 != 0
+}
+
+// Returns (quotient, remainder), or None for a divide error.
+fn divide(operation: &Operation, dividend: u32, divisor: u16) -> Option<(u16, u16)> {
+    if divisor == 0 {
+        return None;
+    }
+
+    if let Operation::Idiv = operation {
+        let dividend = dividend as i32;
+        let divisor = divisor as i16 as i32;
+        let quotient = dividend.checked_div(divisor)?;
+
+        // The 8086 also faults on a quotient of -32768, later CPUs don't.
+        if !(-0x7FFF..=0x7FFF).contains(&quotient) {
+            return None;
+        }
+        return Some((quotient as u16, (dividend % divisor) as u16));
+    }
+
+    let quotient = dividend / divisor as u32;
+    if quotient > 0xFFFF {
+        return None;
+    }
+    Some((quotient as u16, (dividend % divisor as u32) as u16))
+}
+
+fn is_odd(value: usize) -> bool {
     value & 1 == 1
 }
 
 const PAD_AMOUNT: usize = 30;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Programs go above the vector table.
+    const SEGMENT: u16 = 0x100;
+
+    fn load(program: &[u8]) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.set_program_at(SEGMENT, program).unwrap();
+        cpu
+    }
+
+    fn step(cpu: &mut CPU) -> usize {
+        let instruction = cpu.fetch(cpu.ip_address()).unwrap();
+        cpu.simulate(&instruction).unwrap()
+    }
+
+    // The word |index| positions from the top of the stack.
+    fn stack(cpu: &CPU, index: u16) -> u16 {
+        cpu.loadu16(physical_address(cpu.ss(), cpu.sp() + 2 * index))
+    }
+
+    #[test]
+    fn test_int_and_iret() {
+        #[rustfmt::skip]
+        let mut cpu = load(&[
+            0xB8, 0x01, 0x00, // mov ax, 1
+            0xCD, 0x80,       // int 128
+            0x05, 0x02, 0x00, // add ax, 2
+            0xBB, 0x05, 0x00, // handler: mov bx, 5
+            0xCF,             // iret
+        ]);
+        cpu.set_interrupt_vector(0x80, SEGMENT, 8);
+        cpu.flags.i = true;
+
+        step(&mut cpu);
+        assert_eq!(step(&mut cpu), 51);
+        assert_eq!((cpu.cs(), cpu.ip()), (SEGMENT, 8));
+        assert_eq!(cpu.sp(), 0xFFFA);
+        assert_eq!(stack(&cpu, 0), 5);
+        assert_eq!(stack(&cpu, 1), SEGMENT);
+        assert_eq!(stack(&cpu, 2), FLAGS_RESERVED | FLAG_I);
+        assert!(!cpu.flags.i);
+
+        step(&mut cpu);
+        step(&mut cpu);
+        step(&mut cpu);
+        assert_eq!((cpu.ax(), cpu.bx(), cpu.sp(), cpu.ip()), (3, 5, 0, 8));
+        assert!(cpu.flags.i);
+    }
+
+    #[test]
+    fn test_scheduled_interrupt_waits_for_if() {
+        #[rustfmt::skip]
+        let mut cpu = load(&[
+            0xB9, 0x03, 0x00, // mov cx, 3
+            0xFB,             // sti
+            0x83, 0xE9, 0x01, // sub cx, 1
+            0x75, 0xFB,       // jne back to the sub
+            0xBA, 0x07, 0x00, // handler: mov dx, 7
+            0xCF,             // iret
+        ]);
+        cpu.set_interrupt_vector(0x40, SEGMENT, 9);
+        cpu.raise_interrupt_at(1, 0x40);
+
+        step(&mut cpu);
+        assert_eq!(cpu.ip(), 3);
+
+        // Not taken right after sti, but after the instruction that follows it, with the cycles to
+        // enter the handler.
+        assert_eq!(step(&mut cpu), 2);
+        assert_eq!(cpu.ip(), 4);
+        assert_eq!(step(&mut cpu), 4 + EXTERNAL_INTERRUPT_CYCLES);
+        assert_eq!((cpu.ip(), stack(&cpu, 0), cpu.cx()), (9, 7, 2));
+
+        while cpu.ip() != 9 || cpu.executed_instructions() < 4 {
+            step(&mut cpu);
+        }
+        assert_eq!((cpu.cx(), cpu.dx(), cpu.executed_instructions()), (0, 7, 10));
+    }
+
+    #[test]
+    fn test_divide_error() {
+        #[rustfmt::skip]
+        let mut cpu = load(&[
+            0xB8, 0x0A, 0x00, // mov ax, 10
+            0xB9, 0x00, 0x00, // mov cx, 0
+            0xF7, 0xF1,       // div cx
+            0xBB, 0xFF, 0x00, // handler: mov bx, 255
+            0xCF,             // iret
+        ]);
+        cpu.set_interrupt_vector(DIVIDE_ERROR, SEGMENT, 8);
+
+        step(&mut cpu);
+        step(&mut cpu);
+        assert_eq!(step(&mut cpu), 144 + DIVIDE_ERROR_CYCLES);
+
+        // The pushed IP is the one after the div.
+        assert_eq!((cpu.ip(), stack(&cpu, 0)), (8, 8));
+        assert_eq!(cpu.ax(), 10);
+    }
+
+    #[test]
+    fn test_divide() {
+        assert_eq!(divide(&Operation::Div, 100, 7), Some((14, 2)));
+        assert_eq!(divide(&Operation::Div, 0x7_0000, 7), None);
+        assert_eq!(divide(&Operation::Div, 100, 0), None);
+
+        // -100 / 7
+        let dividend = -100i32 as u32;
+        assert_eq!(divide(&Operation::Idiv, dividend, 7), Some((-14i16 as u16, -2i16 as u16)));
+        // -32768 doesn't fit on an 8086.
+        assert_eq!(divide(&Operation::Idiv, 0x8000, -1i16 as u16), None);
+        assert_eq!(divide(&Operation::Idiv, i32::MIN as u32, -1i16 as u16), None);
+    }
+
+    #[test]
+    fn test_single_step() {
+        #[rustfmt::skip]
+        let mut cpu = load(&[
+            0xB8, 0x01, 0x00, // mov ax, 1
+            0xBB, 0x02, 0x00, // mov bx, 2
+            0xCF,             // handler: iret
+        ]);
+        cpu.set_interrupt_vector(SINGLE_STEP, SEGMENT, 6);
+        cpu.flags.t = true;
+
+        assert_eq!(step(&mut cpu), 4 + SINGLE_STEP_CYCLES);
+        assert_eq!((cpu.ip(), stack(&cpu, 0)), (6, 3));
+        assert!(!cpu.flags.t);
+
+        // The handler runs without trapping, and iret restores TF.
+        step(&mut cpu);
+        assert_eq!(cpu.ip(), 3);
+        assert!(cpu.flags.t);
+
+        step(&mut cpu);
+        assert_eq!((cpu.ip(), stack(&cpu, 0), cpu.bx()), (6, 6, 2));
+    }
+
+    #[test]
+    fn test_into() {
+        #[rustfmt::skip]
+        let mut cpu = load(&[
+            0xB8, 0x00, 0x80, // mov ax, 0x8000
+            0x2D, 0x01, 0x00, // sub ax, 1
+            0xCE,             // into
+            0xCF,             // handler: iret
+        ]);
+        cpu.set_interrupt_vector(OVERFLOW, SEGMENT, 7);
+
+        step(&mut cpu);
+        step(&mut cpu);
+        assert!(cpu.flags.o);
+        assert_eq!(step(&mut cpu), 53);
+        assert_eq!((cpu.ip(), stack(&cpu, 0)), (7, 7));
+    }
+
+    #[test]
+    fn test_jumps() {
+        #[rustfmt::skip]
+        let mut cpu = load(&[
+            0xB9, 0x03, 0x00, // mov cx, 3
+            0x83, 0xC0, 0x01, // top: add ax, 1
+            0xE2, 0xFB,       // loop top
+            0xE3, 0x03,       // jcxz end
+            0xBB, 0x01, 0x00, // mov bx, 1
+            0x72, 0x00,       // end: jb $+2
+        ]);
+
+        let mut cycles = vec![];
+        while cpu.ip() < 13 {
+            cycles.push(step(&mut cpu));
+        }
+        assert_eq!((cpu.ax(), cpu.bx(), cpu.cx()), (3, 0, 0));
+        // Loops back twice, falls through, then jcxz is taken.
+        assert_eq!(cycles.len(), 8);
+        assert_eq!([cycles[2], cycles[4], cycles[6], cycles[7]], [17, 17, 5, 18]);
+
+        // We don't have the carry flag to decide.
+        let instruction = cpu.fetch(cpu.ip_address()).unwrap();
+        assert!(matches!(
+            cpu.simulate(&instruction),
+            Err(IntelError::UnsupportedSimulationOperation(_))
+        ));
+    }
+
+    #[test]
+    fn test_timer_interrupt_while_spinning() {
+        #[rustfmt::skip]
+        let mut cpu = load(&[
+            0xB8, 0x34, 0x00, // mov ax, 0x34: counter 0, rate generator
+            0xE6, 0x43,       // out 0x43, al
+            0xB8, 0x64, 0x00, // mov ax, 100
+            0xE6, 0x40,       // out 0x40, al
+            0xB8, 0x00, 0x00, // mov ax, 0
+            0xE6, 0x40,       // out 0x40, al
+            0xFB,             // sti
+            0xEB, 0xFE,       // jmp $
+            0xBB, 0x01, 0x00, // handler: mov bx, 1
+            0xB8, 0x20, 0x00, // mov ax, 0x20
+            0xE6, 0x20,       // out 0x20, al: end of interrupt
+            0xCF,             // iret
+        ]);
+        cpu.io = IoBus::pc([]);
+        cpu.set_interrupt_vector(8, SEGMENT, 18);
+
+        let mut steps = 0;
+        while cpu.ip() != 18 {
+            step(&mut cpu);
+            steps += 1;
+            assert!(steps < 1000, "the timer never interrupted the jmp");
+        }
+        assert!(steps > 8, "{}", steps);
+        assert_eq!((stack(&cpu, 0), cpu.bx()), (16, 0));
+
+        // Back to spinning until the next tick.
+        for _ in 0..4 {
+            step(&mut cpu);
+        }
+        assert_eq!((cpu.ip(), cpu.bx()), (16, 1));
+    }
+
+    #[test]
+    fn test_timer_interrupt() {
+        #[rustfmt::skip]
+        let mut cpu = load(&[
+            0xB8, 0x34, 0x00, // mov ax, 0x34: counter 0, rate generator
+            0xE6, 0x43,       // out 0x43, al
+            0xB8, 0x03, 0x00, // mov ax, 3
+            0xE6, 0x40,       // out 0x40, al
+            0xB8, 0x00, 0x00, // mov ax, 0
+            0xE6, 0x40,       // out 0x40, al
+            0xFB,             // sti
+            0x83, 0xC1, 0x01, // add cx, 1
+            0xBB, 0x01, 0x00, // handler: mov bx, 1
+            0xB8, 0x20, 0x00, // mov ax, 0x20
+            0xE6, 0x20,       // out 0x20, al: end of interrupt
+            0xCF,             // iret
+        ]);
+        cpu.io = IoBus::pc([]);
+        cpu.set_interrupt_vector(8, SEGMENT, 0x13);
+
+        // The counter expires during sti, but IRQ 0 waits for the add after it.
+        for _ in 0..7 {
+            step(&mut cpu);
+        }
+        assert_eq!((cpu.ip(), cpu.cx()), (0x10, 0));
+        step(&mut cpu);
+        assert_eq!((cpu.ip(), stack(&cpu, 0), cpu.cx()), (0x13, 0x13, 1));
+
+        // The timer kept going while in the handler, so iret lands right back in it.
+        for _ in 0..4 {
+            step(&mut cpu);
+        }
+        assert_eq!((cpu.ip(), stack(&cpu, 0), cpu.bx()), (0x13, 0x13, 1));
+        assert_eq!(cpu.cx(), 1);
+    }
+}
@@ -0,0 +1,138 @@
+use super::cpu::*;
+use super::error::IntelError;
+use super::instructions::*;
+use super::registers::*;
+use super::video::render_text_buffer;
+use std::io::{BufRead, Write};
+
+// A line debugger that stops after every instruction of a simulation and reads commands:
+//
+// s, step (or nothing)  run the next instruction
+// c, continue           run to the end without stopping again
+// r, regs               print the registers and flags
+// screen, ansi          print the CGA text buffer, ansi with colors
+pub struct Debugger<R, W> {
+    input: R,
+    output: W,
+    stopping: bool,
+}
+
+// The order in which the registers are printed.
+const REGISTERS: [&str; 13] = [
+    "ax", "bx", "cx", "dx", "sp", "bp", "si", "di", "ip", "es", "cs", "ss", "ds",
+];
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Debugger {
+            input,
+            output,
+            stopping: true,
+        }
+    }
+
+    // Called once |instruction| ran. Reads commands until one of them lets the program go on.
+    pub fn after_instruction(
+        &mut self,
+        cpu: &CPU,
+        instruction: &Instruction,
+    ) -> Result<(), IntelError> {
+        if !self.stopping {
+            return Ok(());
+        }
+        writeln!(self.output, "{:05X}  {}", instruction.address, instruction)?;
+
+        loop {
+            write!(self.output, "> ")?;
+            self.output.flush()?;
+
+            // Nobody is left to ask once the input ends.
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                self.stopping = false;
+                return Ok(());
+            }
+
+            match line.trim() {
+                "" | "s" | "step" => return Ok(()),
+                "c" | "continue" => {
+                    self.stopping = false;
+                    return Ok(());
+                }
+                "r" | "regs" => writeln!(self.output, "{}", registers(cpu))?,
+                "screen" => write!(self.output, "{}", render_text_buffer(cpu.get_memory(), false))?,
+                "ansi" => write!(self.output, "{}", render_text_buffer(cpu.get_memory(), true))?,
+                command => writeln!(self.output, "unknown command: {}", command)?,
+            }
+        }
+    }
+}
+
+// ax: 0001 bx: 0000 ... ds: 0000 flags: Z
+fn registers(cpu: &CPU) -> String {
+    let mut values: Vec<String> = REGISTERS
+        .iter()
+        .map(|name| {
+            let register = Register::find(name).unwrap();
+            format!("{}: {:04X}", name, cpu.get_register(&register))
+        })
+        .collect();
+    values.push(format!("flags: {}", cpu.print_flags()));
+    values.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intel8086::{simulate_with, SimulationOptions};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Output the test can look at after the simulation took the debugger.
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_screen_on_demand() {
+        #[rustfmt::skip]
+        let program = [
+            0xB8, 0x00, 0xB8,                   // mov ax, 0xB800
+            0x8E, 0xD8,                         // mov ds, ax
+            0xC7, 0x06, 0x00, 0x00, 0x48, 0x07, // mov word [0], 0x0748
+            0xC7, 0x06, 0x02, 0x00, 0x69, 0x07, // mov word [2], 0x0769
+        ];
+
+        let output = SharedOutput::default();
+        let input = "\nregs\ns\nscreen\ncontinue\n".as_bytes();
+        let mut debugger = Debugger::new(input, output.clone());
+        let options = SimulationOptions {
+            on_instruction: Some(Box::new(move |cpu, instruction| {
+                debugger.after_instruction(cpu, instruction)
+            })),
+            ..Default::default()
+        };
+        let result = simulate_with(&program, options).unwrap();
+        assert_eq!(result.executed_instructions.len(), 4);
+
+        let output = String::from_utf8(output.0.take()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "00000  mov ax, 47104");
+        assert_eq!(lines[1], "> 00003  mov ds, ax");
+        assert!(lines[2].starts_with("> ax: B800 bx: 0000"), "{}", lines[2]);
+        assert_eq!(lines[3], "> 00005  mov word [0], 1864");
+
+        // Only the first character is there by the third instruction.
+        assert_eq!(lines[4], "> H");
+        assert_eq!(lines.len(), 4 + 25 + 1);
+    }
+}
@@ -46,7 +46,13 @@ pub(super) fn decode_op_immediate_to_register_memory(bytes: &[u8]) -> IntelResul
         return Err(IntelError::IncompleteByteStream);
     }
 
-    let operation = decode_op((bytes[1] >> 3) & 0b111)?;
+    // Mov has its own immediate opcode (1100011w). In this group 0b001 is "or".
+    let op = (bytes[1] >> 3) & 0b111;
+    if op == 0b001 {
+        return Err(IntelError::UnsupportedOperation(op));
+    }
+
+    let operation = decode_op(op)?;
     decode_immediate_to_register_memory(bytes, operation)
 }
 
@@ -147,6 +153,103 @@ pub(super) fn decode_mov_accumulator_to_from_memory(bytes: &[u8], direction: boo
     Ok(instruction)
 }
 
+// mov sreg, r/m16 (8E) and mov r/m16, sreg (8C). The reg field holds the segment register.
+pub(super) fn decode_mov_segment(bytes: &[u8], to_segment: bool) -> IntelResult {
+    let mut instruction = Instruction::new();
+    instruction.consume(bytes, 2)?;
+
+    instruction.bits.set_d(to_segment);
+    instruction.bits.set_w(true);
+    instruction.bits.set_vmod(instruction.data[1] >> 6);
+    instruction.bits.set_rm(instruction.data[1] & 0b111);
+
+    let sr = (instruction.data[1] >> 3) & 0b111;
+    let Some(segment) = SEGMENT_REGISTERS.get(sr as usize) else {
+        return Err(IntelError::UnsupportedOperation(sr));
+    };
+    let segment = Operand::Register(*segment);
+
+    let operand = consume_displacement(bytes, &mut instruction)?;
+    let (src, dst) = if to_segment {
+        (operand, segment)
+    } else {
+        (segment, operand)
+    };
+
+    instruction.operation = Operation::Mov;
+    instruction.src = src;
+    instruction.dst = dst;
+
+    Ok(instruction)
+}
+
+// in al/ax, port and out port, al/ax. The port is either an 8 bit immediate or dx.
+pub(super) fn decode_in_out(bytes: &[u8], operation: Operation, port_in_dx: bool) -> IntelResult {
+    let mut instruction = Instruction::new();
+    instruction.consume(bytes, 1)?;
+
+    instruction.bits.set_w((instruction.data[0] & 0b1) != 0);
+    let accum = Operand::Register(Register::interpret_accumulator(instruction.bits.w()));
+
+    let port = if port_in_dx {
+        Operand::Register(REGISTER_DX)
+    } else {
+        instruction.consume(bytes, 1)?;
+        Operand::Immediate(instruction.data[1] as u16)
+    };
+
+    let (src, dst) = match operation {
+        Operation::In => (port, accum),
+        _ => (accum, port),
+    };
+
+    instruction.operation = operation;
+    instruction.src = src;
+    instruction.dst = dst;
+
+    Ok(instruction)
+}
+
+// div/idiv r/m. The dividend is implicit (ax or dx:ax), so the divisor is the only operand.
+pub(super) fn decode_division(bytes: &[u8]) -> IntelResult {
+    let mut instruction = Instruction::new();
+    instruction.consume(bytes, 2)?;
+
+    instruction.bits.set_w((instruction.data[0] & 0b1) != 0);
+    instruction.bits.set_vmod(instruction.data[1] >> 6);
+    instruction.bits.set_rm(instruction.data[1] & 0b111);
+
+    let op = (instruction.data[1] >> 3) & 0b111;
+    instruction.operation = match op {
+        0b110 => Operation::Div,
+        0b111 => Operation::Idiv,
+        _ => return Err(IntelError::UnsupportedOperation(op)),
+    };
+    instruction.dst = consume_displacement(bytes, &mut instruction)?;
+
+    Ok(instruction)
+}
+
+// int imm8. The interrupt number goes in dst, as the only operand.
+pub(super) fn decode_interrupt(bytes: &[u8]) -> IntelResult {
+    let mut instruction = Instruction::new();
+    instruction.consume(bytes, 2)?;
+
+    instruction.operation = Operation::Int;
+    instruction.dst = Operand::Immediate(instruction.data[1] as u16);
+
+    Ok(instruction)
+}
+
+pub(super) fn decode_no_operands(bytes: &[u8], operation: Operation) -> IntelResult {
+    let mut instruction = Instruction::new();
+    instruction.consume(bytes, 1)?;
+
+    instruction.operation = operation;
+
+    Ok(instruction)
+}
+
 pub(super) fn decode_jump(bytes: &[u8], jump: &JumpDescription) -> IntelResult {
     let mut instruction = Instruction::new();
     instruction.consume(bytes, 2)?;
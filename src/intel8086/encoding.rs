@@ -0,0 +1,491 @@
+use super::error::*;
+use super::instructions::*;
+use super::registers::*;
+
+pub(super) type EncodeResult = Result<Vec<u8>, IntelError>;
+
+pub(super) fn encode_instruction(instruction: &Instruction) -> EncodeResult {
+    match &instruction.operation {
+        Operation::Mov => encode_mov(instruction),
+        Operation::Add | Operation::Sub | Operation::Cmp => encode_op(instruction),
+        Operation::Jump(jump) => encode_jump(instruction, jump),
+        Operation::In | Operation::Out => encode_in_out(instruction),
+        Operation::Div | Operation::Idiv => encode_division(instruction),
+        Operation::Int => encode_interrupt(instruction),
+        Operation::Int3 => Ok(vec![0xCC]),
+        Operation::Into => Ok(vec![0xCE]),
+        Operation::Iret => Ok(vec![0xCF]),
+        Operation::Cli => Ok(vec![0xFA]),
+        Operation::Sti => Ok(vec![0xFB]),
+        Operation::Invalid => Err(IntelError::InvalidInstruction(instruction.clone())),
+    }
+}
+
+fn encode_mov(instruction: &Instruction) -> EncodeResult {
+    let w = is_wide(instruction)?;
+
+    match (&instruction.dst, &instruction.src) {
+        // Segment registers go in the reg field of their own opcodes.
+        (Operand::Register(segment), rm) | (rm, Operand::Register(segment))
+            if segment.segment_encoding().is_some() =>
+        {
+            let to_segment = matches!(&instruction.dst, Operand::Register(r) if r == segment);
+            let mut bytes = vec![if to_segment { 0x8E } else { 0x8C }];
+            push_modrm(instruction, &mut bytes, segment.segment_encoding().unwrap(), rm)?;
+            Ok(bytes)
+        }
+        // Immediate to register has its own short form.
+        (Operand::Register(register), Operand::Immediate(value)) => {
+            let reg = encode_register(instruction, register)?;
+            let mut bytes = vec![0b1011_0000 | ((w as u8) << 3) | reg];
+            push_immediate(&mut bytes, *value, w);
+            Ok(bytes)
+        }
+        // Accumulator to/from direct memory.
+        (Operand::Register(register), Operand::EAC(EAC::DirectAccess(address)))
+            if is_accumulator(register) =>
+        {
+            let mut bytes = vec![0b1010_0000 | w as u8];
+            push_u16(&mut bytes, *address);
+            Ok(bytes)
+        }
+        (Operand::EAC(EAC::DirectAccess(address)), Operand::Register(register))
+            if is_accumulator(register) =>
+        {
+            let mut bytes = vec![0b1010_0010 | w as u8];
+            push_u16(&mut bytes, *address);
+            Ok(bytes)
+        }
+        // Immediate to memory.
+        (Operand::EAC(_), Operand::Immediate(value)) => {
+            let mut bytes = vec![0b1100_0110 | w as u8];
+            push_modrm(instruction, &mut bytes, 0b000, &instruction.dst)?;
+            push_immediate(&mut bytes, *value, w);
+            Ok(bytes)
+        }
+        _ => encode_register_memory_to_from_either(instruction, 0b1000_1000, w),
+    }
+}
+
+fn encode_op(instruction: &Instruction) -> EncodeResult {
+    let w = is_wide(instruction)?;
+    let op = encode_op_bits(instruction)?;
+
+    let Operand::Immediate(value) = &instruction.src else {
+        return encode_register_memory_to_from_either(instruction, op << 3, w);
+    };
+
+    // NASM prefers the sign-extended byte form whenever the value fits, even over the
+    // accumulator form (both are 3 bytes for word accumulators).
+    let s = w && fits_in_i8(*value);
+
+    if let Operand::Register(register) = &instruction.dst {
+        if is_accumulator(register) && !s {
+            let mut bytes = vec![(op << 3) | 0b100 | w as u8];
+            push_immediate(&mut bytes, *value, w);
+            return Ok(bytes);
+        }
+    }
+
+    if !matches!(instruction.dst, Operand::Register(_) | Operand::EAC(_)) {
+        return Err(IntelError::InvalidInstruction(instruction.clone()));
+    }
+
+    let mut bytes = vec![0b1000_0000 | ((s as u8) << 1) | w as u8];
+    push_modrm(instruction, &mut bytes, op, &instruction.dst)?;
+    push_immediate(&mut bytes, *value, w && !s);
+    Ok(bytes)
+}
+
+fn encode_jump(instruction: &Instruction, jump: &JumpDescription) -> EncodeResult {
+    let Operand::JumpOffset(offset) = instruction.src else {
+        return Err(IntelError::InvalidInstruction(instruction.clone()));
+    };
+
+    Ok(vec![jump.opcode, offset as u8])
+}
+
+// |opcode| is the first byte with the d and w bits cleared.
+fn encode_register_memory_to_from_either(
+    instruction: &Instruction,
+    opcode: u8,
+    w: bool,
+) -> EncodeResult {
+    // The d bit tells whether the reg field is the destination.
+    // NASM puts the register in the reg field as source when both operands are registers.
+    let (d, register, rm) = match (&instruction.dst, &instruction.src) {
+        (_, Operand::Register(register)) if !matches!(instruction.dst, Operand::Immediate(_)) => {
+            (false, register, &instruction.dst)
+        }
+        (Operand::Register(register), Operand::EAC(_)) => (true, register, &instruction.src),
+        _ => return Err(IntelError::InvalidInstruction(instruction.clone())),
+    };
+
+    let reg = encode_register(instruction, register)?;
+
+    let mut bytes = vec![opcode | ((d as u8) << 1) | w as u8];
+    push_modrm(instruction, &mut bytes, reg, rm)?;
+    Ok(bytes)
+}
+
+// HELPERS -----------------------------------------------------------------------------------------
+
+// Pushes the mod/reg/rm byte and any displacement that |rm| needs.
+fn push_modrm(
+    instruction: &Instruction,
+    bytes: &mut Vec<u8>,
+    reg: u8,
+    rm: &Operand,
+) -> Result<(), IntelError> {
+    let (vmod, rm, displacement) = match rm {
+        Operand::Register(register) => (0b11, encode_register(instruction, register)?, None),
+        Operand::EAC(EAC::DirectAccess(address)) => (0b00, 0b110, Some((*address, true))),
+        Operand::EAC(eac) => {
+            let (rm, offset) = decompose_eac(eac);
+
+            // [bp] has no zero-displacement form, as that encoding is taken by direct access.
+            if offset == 0 && rm != 0b110 {
+                (0b00, rm, None)
+            } else if fits_in_i8(offset) {
+                (0b01, rm, Some((offset, false)))
+            } else {
+                (0b10, rm, Some((offset, true)))
+            }
+        }
+        _ => return Err(IntelError::InvalidInstruction(instruction.clone())),
+    };
+
+    bytes.push((vmod << 6) | (reg << 3) | rm);
+
+    match displacement {
+        Some((value, true)) => push_u16(bytes, value),
+        Some((value, false)) => bytes.push(value as u8),
+        None => {}
+    }
+
+    Ok(())
+}
+
+fn decompose_eac(eac: &EAC) -> (u8, u16) {
+    match eac {
+        EAC::BxSi(offset) => (0b000, *offset),
+        EAC::BxDi(offset) => (0b001, *offset),
+        EAC::BpSi(offset) => (0b010, *offset),
+        EAC::BpDi(offset) => (0b011, *offset),
+        EAC::Si(offset) => (0b100, *offset),
+        EAC::Di(offset) => (0b101, *offset),
+        EAC::Bp(offset) => (0b110, *offset),
+        EAC::Bx(offset) => (0b111, *offset),
+        EAC::DirectAccess(address) => (0b110, *address),
+    }
+}
+
+fn encode_register(instruction: &Instruction, register: &Register) -> Result<u8, IntelError> {
+    register
+        .encoding()
+        .ok_or(IntelError::InvalidInstruction(instruction.clone()))
+}
+
+fn encode_in_out(instruction: &Instruction) -> EncodeResult {
+    let w = is_wide(instruction)?;
+    let (accum, port, direction) = match instruction.operation {
+        Operation::In => (&instruction.dst, &instruction.src, 0b00),
+        _ => (&instruction.src, &instruction.dst, 0b10),
+    };
+
+    if !matches!(accum, Operand::Register(register) if is_accumulator(register)) {
+        return Err(IntelError::InvalidInstruction(instruction.clone()));
+    }
+
+    match port {
+        Operand::Immediate(port) if *port <= 0xFF => {
+            Ok(vec![0b1110_0100 | direction | w as u8, *port as u8])
+        }
+        Operand::Register(register) if *register == REGISTER_DX => {
+            Ok(vec![0b1110_1100 | direction | w as u8])
+        }
+        _ => Err(IntelError::InvalidInstruction(instruction.clone())),
+    }
+}
+
+fn encode_division(instruction: &Instruction) -> EncodeResult {
+    let w = is_wide(instruction)?;
+    let op = match instruction.operation {
+        Operation::Div => 0b110,
+        _ => 0b111,
+    };
+
+    if !matches!(instruction.dst, Operand::Register(_) | Operand::EAC(_)) {
+        return Err(IntelError::InvalidInstruction(instruction.clone()));
+    }
+
+    let mut bytes = vec![0b1111_0110 | w as u8];
+    push_modrm(instruction, &mut bytes, op, &instruction.dst)?;
+    Ok(bytes)
+}
+
+fn encode_interrupt(instruction: &Instruction) -> EncodeResult {
+    match instruction.dst {
+        Operand::Immediate(vector) if vector <= 0xFF => Ok(vec![0xCD, vector as u8]),
+        _ => Err(IntelError::InvalidInstruction(instruction.clone())),
+    }
+}
+
+fn encode_op_bits(instruction: &Instruction) -> Result<u8, IntelError> {
+    match instruction.operation {
+        Operation::Add => Ok(0b000),
+        Operation::Sub => Ok(0b101),
+        Operation::Cmp => Ok(0b111),
+        _ => Err(IntelError::InvalidInstruction(instruction.clone())),
+    }
+}
+
+// The operand size comes from any register operand. Otherwise we rely on the w bit, which is what
+// the "byte"/"word" specifier represents when printing.
+pub(super) fn is_wide(instruction: &Instruction) -> Result<bool, IntelError> {
+    // The port in dx doesn't tell the size of the transfer, only the accumulator does.
+    match (&instruction.operation, &instruction.dst, &instruction.src) {
+        (Operation::In, Operand::Register(accum), _)
+        | (Operation::Out, _, Operand::Register(accum)) => return Ok(accum.len() == 2),
+        _ => {}
+    }
+
+    match (&instruction.dst, &instruction.src) {
+        (Operand::Register(dst), Operand::Register(src)) => {
+            if dst.len() != src.len() {
+                return Err(IntelError::InvalidInstruction(instruction.clone()));
+            }
+            Ok(dst.len() == 2)
+        }
+        (Operand::Register(register), _) | (_, Operand::Register(register)) => {
+            Ok(register.len() == 2)
+        }
+        _ => Ok(instruction.bits.w()),
+    }
+}
+
+fn is_accumulator(register: &Register) -> bool {
+    *register == REGISTER_AX || *register == REGISTER_AL
+}
+
+fn fits_in_i8(value: u16) -> bool {
+    let value = value as i16;
+    (i8::MIN as i16..=i8::MAX as i16).contains(&value)
+}
+
+fn push_immediate(bytes: &mut Vec<u8>, value: u16, wide: bool) {
+    if wide {
+        push_u16(bytes, value);
+    } else {
+        bytes.push(value as u8);
+    }
+}
+
+fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> Instruction {
+        Instruction::decode(bytes).unwrap()
+    }
+
+    mod canonical {
+        use super::*;
+
+        #[test]
+        fn test_register_to_register() {
+            // mov cx, bx
+            assert_eq!(decode(&[0x89, 0xD9]).encode().unwrap(), vec![0x89, 0xD9]);
+            // mov ch, ah
+            assert_eq!(decode(&[0x88, 0xE5]).encode().unwrap(), vec![0x88, 0xE5]);
+        }
+
+        #[test]
+        fn test_register_to_register_uses_source_in_reg_field() {
+            // mov cx, bx with the d bit set.
+            assert_eq!(decode(&[0x8B, 0xCB]).encode().unwrap(), vec![0x89, 0xD9]);
+        }
+
+        #[test]
+        fn test_shortest_displacement() {
+            // mov al, [bx + si + 4] with a 16-bit displacement.
+            let bytes = [0x8A, 0x80, 0x04, 0x00];
+            assert_eq!(decode(&bytes).encode().unwrap(), vec![0x8A, 0x40, 0x04]);
+
+            // mov dx, [bp] always needs a displacement.
+            let bytes = [0x8B, 0x56, 0x00];
+            assert_eq!(decode(&bytes).encode().unwrap(), vec![0x8B, 0x56, 0x00]);
+
+            // mov bx, [bp + di] with an 8-bit zero displacement.
+            let bytes = [0x8B, 0x5B, 0x00];
+            assert_eq!(decode(&bytes).encode().unwrap(), vec![0x8B, 0x1B]);
+        }
+
+        #[test]
+        fn test_accumulator_direct_access() {
+            // mov ax, [2321] through the generic form.
+            let bytes = [0x8B, 0x06, 0x11, 0x09];
+            assert_eq!(decode(&bytes).encode().unwrap(), vec![0xA1, 0x11, 0x09]);
+
+            // mov [22], ax
+            let bytes = [0xA3, 0x16, 0x00];
+            assert_eq!(decode(&bytes).encode().unwrap(), bytes.to_vec());
+        }
+
+        #[test]
+        fn test_segment_registers() {
+            // mov ds, ax / mov es, [bp + 2] / mov [1000], cs
+            for bytes in [
+                &[0x8E, 0xD8][..],
+                &[0x8E, 0x46, 0x02],
+                &[0x8C, 0x0E, 0xE8, 0x03],
+            ] {
+                let instruction = decode(bytes);
+                assert_eq!(instruction.encode().unwrap(), bytes.to_vec(), "{}", instruction);
+            }
+
+            assert_eq!(decode(&[0x8E, 0xD8]).to_string(), "mov ds, ax");
+            assert_eq!(decode(&[0x8E, 0x46, 0x02]).to_string(), "mov es, [bp + 2]");
+            assert_eq!(decode(&[0x8C, 0x0E, 0xE8, 0x03]).to_string(), "mov [1000], cs");
+
+            // There are only 4 segment registers.
+            assert!(Instruction::decode(&[0x8E, 0xE0]).is_err());
+        }
+
+        #[test]
+        fn test_in_out() {
+            // in al, 96 / in ax, dx / out 67, al / out dx, ax
+            for bytes in [&[0xE4, 0x60][..], &[0xED], &[0xE6, 0x43], &[0xEF]] {
+                let instruction = decode(bytes);
+                assert_eq!(instruction.encode().unwrap(), bytes.to_vec(), "{}", instruction);
+            }
+
+            assert_eq!(decode(&[0xE4, 0x60]).to_string(), "in al, 96");
+            assert_eq!(decode(&[0xED]).to_string(), "in ax, dx");
+            assert_eq!(decode(&[0xE6, 0x43]).to_string(), "out 67, al");
+            assert_eq!(decode(&[0xEF]).to_string(), "out dx, ax");
+        }
+
+        #[test]
+        fn test_division() {
+            // div cx / idiv word [bx + si + 4] / div byte [1000]
+            for bytes in [
+                &[0xF7, 0xF1][..],
+                &[0xF7, 0x78, 0x04],
+                &[0xF6, 0x36, 0xE8, 0x03],
+            ] {
+                let instruction = decode(bytes);
+                assert_eq!(instruction.encode().unwrap(), bytes.to_vec(), "{}", instruction);
+            }
+
+            assert_eq!(decode(&[0xF7, 0xF1]).to_string(), "div cx");
+            assert_eq!(decode(&[0xF7, 0x78, 0x04]).to_string(), "idiv word [bx + si + 4]");
+            assert_eq!(decode(&[0xF6, 0x36, 0xE8, 0x03]).to_string(), "div byte [1000]");
+        }
+
+        #[test]
+        fn test_interrupts() {
+            for bytes in [
+                &[0xCD, 0x21][..],
+                &[0xCC],
+                &[0xCE],
+                &[0xCF],
+                &[0xFA],
+                &[0xFB],
+            ] {
+                let instruction = decode(bytes);
+                assert_eq!(instruction.encode().unwrap(), bytes.to_vec(), "{}", instruction);
+            }
+
+            assert_eq!(decode(&[0xCD, 0x21]).to_string(), "int 33");
+            assert_eq!(decode(&[0xCC]).to_string(), "int3");
+            assert_eq!(decode(&[0xCF]).to_string(), "iret");
+        }
+
+        #[test]
+        fn test_immediate_forms() {
+            // mov cx, -12
+            assert_eq!(decode(&[0xB9, 0xF4, 0xFF]).encode().unwrap(), vec![0xB9, 0xF4, 0xFF]);
+            // add byte [bx], 34
+            assert_eq!(decode(&[0x80, 0x07, 0x22]).encode().unwrap(), vec![0x80, 0x07, 0x22]);
+            // add word [bp + si + 1000], 29 with a full immediate.
+            let bytes = [0x81, 0x82, 0xE8, 0x03, 0x1D, 0x00];
+            assert_eq!(decode(&bytes).encode().unwrap(), vec![0x83, 0x82, 0xE8, 0x03, 0x1D]);
+        }
+
+        #[test]
+        fn test_accumulator_immediate() {
+            // add ax, 1000
+            assert_eq!(decode(&[0x05, 0xE8, 0x03]).encode().unwrap(), vec![0x05, 0xE8, 0x03]);
+            // add al, 9 through the generic form.
+            assert_eq!(decode(&[0x80, 0xC0, 0x09]).encode().unwrap(), vec![0x04, 0x09]);
+            // cmp ax, 2 prefers the sign-extended form.
+            assert_eq!(decode(&[0x3D, 0x02, 0x00]).encode().unwrap(), vec![0x83, 0xF8, 0x02]);
+        }
+
+        #[test]
+        fn test_jumps() {
+            assert_eq!(decode(&[0x75, 0xFC]).encode().unwrap(), vec![0x75, 0xFC]);
+            assert_eq!(decode(&[0xE2, 0xF2]).encode().unwrap(), vec![0xE2, 0xF2]);
+        }
+
+        #[test]
+        fn test_invalid() {
+            let instruction = Instruction::new();
+            assert!(instruction.encode().is_err());
+        }
+    }
+
+    mod round_trip {
+        use super::*;
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        fn same_semantics(a: &Instruction, b: &Instruction) -> bool {
+            a.operation == b.operation
+                && a.dst == b.dst
+                && a.src == b.src
+                && is_wide(a).unwrap() == is_wide(b).unwrap()
+        }
+
+        // Feeds random byte streams for every first byte into the decoder. Whatever decodes must
+        // survive decode(encode(i)) with the same meaning, and the canonical form must be stable.
+        #[test]
+        fn test_decode_encode_every_opcode() {
+            let mut rng = StdRng::seed_from_u64(8086);
+            let mut decoded = 0;
+
+            for opcode in 0..=u8::MAX {
+                for _ in 0..256 {
+                    let mut bytes = [0u8; 6];
+                    rng.fill(&mut bytes[..]);
+                    bytes[0] = opcode;
+
+                    let Ok(instruction) = Instruction::decode(&bytes) else {
+                        continue;
+                    };
+                    decoded += 1;
+
+                    let encoded = instruction.encode().unwrap();
+                    let canonical = decode(&encoded);
+                    assert_eq!(canonical.len(), encoded.len(), "{:02X?}", bytes);
+                    assert!(
+                        same_semantics(&instruction, &canonical),
+                        "{:02X?}: {} vs {}",
+                        bytes,
+                        instruction,
+                        canonical
+                    );
+
+                    assert_eq!(canonical.encode().unwrap(), encoded);
+                    assert_eq!(decode(&canonical.encode().unwrap()), canonical);
+                }
+            }
+
+            assert!(decoded > 0);
+        }
+    }
+}
@@ -35,4 +35,16 @@ pub enum IntelError {
 
     #[error("Unknown Register: {0}")]
     UnknownRegister(String),
+
+    #[error("Invalid expectation: {0}")]
+    InvalidExpectation(String),
+
+    #[error("Still running after {0} instructions")]
+    InstructionLimit(usize),
+
+    #[error("Simulation panicked: {0}")]
+    Panicked(String),
+
+    #[error("Reading instructions: {0}")]
+    Io(#[from] std::io::Error),
 }
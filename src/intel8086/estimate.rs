@@ -0,0 +1,353 @@
+use super::encoding::is_wide;
+use super::instructions::*;
+use super::registers::*;
+use super::tables::*;
+use super::traversal::*;
+use std::collections::BTreeMap;
+
+// Static cycle counts, without running the program. Every basic block gets the cost of its
+// instructions, and every loop (a jump back to an earlier block) the cost of one iteration.
+// Where the cost depends on what happens at runtime (branches, odd addresses) we report the best
+// and the worst case.
+
+// Best and worst case, in cycles.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct CostRange {
+    pub best: usize,
+    pub worst: usize,
+}
+
+impl CostRange {
+    pub fn exact(cycles: usize) -> Self {
+        CostRange {
+            best: cycles,
+            worst: cycles,
+        }
+    }
+
+    // The cost of either path.
+    pub fn merge(self, other: CostRange) -> Self {
+        CostRange {
+            best: self.best.min(other.best),
+            worst: self.worst.max(other.worst),
+        }
+    }
+}
+
+impl std::ops::Add for CostRange {
+    type Output = CostRange;
+
+    fn add(self, other: CostRange) -> CostRange {
+        CostRange {
+            best: self.best + other.best,
+            worst: self.worst + other.worst,
+        }
+    }
+}
+
+impl std::fmt::Display for CostRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.best == self.worst {
+            write!(f, "{} cycles", self.best)
+        } else {
+            write!(f, "{}-{} cycles", self.best, self.worst)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, serde::Serialize)]
+pub struct BlockEstimate {
+    pub start: usize,
+    pub end: usize,
+    // Everything but the jump that ends the block, if any.
+    pub body: CostRange,
+    // The jump that ends the block, as (taken, not taken) cycles.
+    pub jump: Option<(usize, usize)>,
+    // Instructions we have no timings for. They count as 0 cycles.
+    pub unknown: Vec<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq, serde::Serialize)]
+pub struct LoopEstimate {
+    // Where the jump back lands.
+    pub start: usize,
+    // One past the jump back.
+    pub end: usize,
+    // From |start| until jumping back to it.
+    pub iteration: CostRange,
+    // From |start| until the jump back falls through. None if it always jumps.
+    pub exit: Option<CostRange>,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct CycleReport {
+    pub blocks: Vec<BlockEstimate>,
+    pub loops: Vec<LoopEstimate>,
+}
+
+impl std::fmt::Display for CycleReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for block in &self.blocks {
+            write!(f, "{:04X}..{:04X}: {}", block.start, block.end, block.body)?;
+            if let Some((taken, not_taken)) = block.jump {
+                write!(f, " + jump ({} taken, {} not taken)", taken, not_taken)?;
+            }
+            if !block.unknown.is_empty() {
+                let unknown: Vec<String> =
+                    block.unknown.iter().map(|a| format!("{:04X}", a)).collect();
+                write!(f, " (no timings for {})", unknown.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+
+        for estimate in &self.loops {
+            write!(
+                f,
+                "loop {:04X}..{:04X}: {} per iteration",
+                estimate.start, estimate.end, estimate.iteration
+            )?;
+            if let Some(exit) = estimate.exit {
+                write!(f, ", {} on the last one", exit)?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn estimate_program(bytes: &[u8]) -> CycleReport {
+    estimate_traversal(&disassemble_recursive(bytes, &[0]))
+}
+
+pub fn estimate_traversal(traversal: &Traversal) -> CycleReport {
+    let blocks: Vec<BlockEstimate> = traversal
+        .blocks
+        .iter()
+        .map(|block| estimate_block(&traversal.instructions[block.instructions.clone()], block))
+        .collect();
+
+    let mut loops = vec![];
+    for (index, block) in traversal.blocks.iter().enumerate() {
+        let last = &traversal.instructions[block.instructions.end - 1];
+        match last.jump_target() {
+            Some(target) if target <= block.start => {
+                loops.push(estimate_loop(traversal, &blocks, target, index));
+            }
+            _ => {}
+        }
+    }
+
+    CycleReport { blocks, loops }
+}
+
+// HELPERS -----------------------------------------------------------------------------------------
+
+fn estimate_block(instructions: &[Instruction], block: &BasicBlock) -> BlockEstimate {
+    let mut estimate = BlockEstimate {
+        start: block.start,
+        end: block.end,
+        body: CostRange::default(),
+        jump: None,
+        unknown: vec![],
+    };
+
+    for instruction in instructions {
+        if let Operation::Jump(jump) = &instruction.operation {
+            estimate.jump = Some(jump_cycles(&jump.jump));
+            continue;
+        }
+
+        match instruction_cost(instruction) {
+            Some(cost) => estimate.body = estimate.body + cost,
+            None => estimate.unknown.push(instruction.address),
+        }
+    }
+
+    estimate
+}
+
+fn instruction_cost(instruction: &Instruction) -> Option<CostRange> {
+    let estimate = estimate_cycles(instruction)?;
+    let mut cost = CostRange::exact(estimate.total());
+
+    // Word transfers from an odd address take 4 more cycles each. We only know the address
+    // when it is direct.
+    if estimate.transfers > 0 && is_wide(instruction).unwrap_or(false) {
+        let penalty = 4 * estimate.transfers;
+        match memory_operand(instruction) {
+            Some(EAC::DirectAccess(address)) if address % 2 == 0 => {}
+            Some(EAC::DirectAccess(_)) => cost = CostRange::exact(cost.best + penalty),
+            _ => cost.worst += penalty,
+        }
+    }
+
+    Some(cost)
+}
+
+fn memory_operand(instruction: &Instruction) -> Option<EAC> {
+    match (&instruction.dst, &instruction.src) {
+        (Operand::EAC(eac), _) | (_, Operand::EAC(eac)) => Some(eac.clone()),
+        _ => None,
+    }
+}
+
+// The loop goes from |start| to the end of the block at |latch|, which jumps back to |start|.
+// Paths only go forward inside the loop, so we walk the blocks in address order keeping the cost
+// of reaching each one. Inner loops count as not taken, and jumps out of the loop from anywhere
+// but the latch are not followed.
+fn estimate_loop(
+    traversal: &Traversal,
+    blocks: &[BlockEstimate],
+    start: usize,
+    latch: usize,
+) -> LoopEstimate {
+    let end = traversal.blocks[latch].end;
+
+    // Cost from |start| to the beginning of each block.
+    let mut reach: BTreeMap<usize, CostRange> = BTreeMap::new();
+    reach.insert(start, CostRange::default());
+
+    let mut iteration: Option<CostRange> = None;
+    let mut exit: Option<CostRange> = None;
+
+    for (block, estimate) in traversal.blocks.iter().zip(blocks) {
+        if block.start < start || block.start >= end {
+            continue;
+        }
+        let Some(&cost) = reach.get(&block.start) else {
+            continue;
+        };
+        let cost = cost + estimate.body;
+
+        let last = &traversal.instructions[block.instructions.end - 1];
+        let (taken, not_taken) = estimate.jump.unwrap_or((0, 0));
+
+        let mut edges = vec![];
+        if let Some(target) = last.jump_target() {
+            edges.push((target, taken));
+        }
+        match &last.operation {
+            Operation::Jump(jump) if jump.jump == Jump::JMP => {}
+            _ => edges.push((block.end, not_taken)),
+        }
+
+        for (successor, cycles) in edges {
+            let cost = cost + CostRange::exact(cycles);
+            if successor == start && block.end == end {
+                iteration = Some(iteration.map_or(cost, |c| c.merge(cost)));
+            } else if successor == end && block.end == end {
+                exit = Some(exit.map_or(cost, |c| c.merge(cost)));
+            } else if successor > block.start && successor < end {
+                reach
+                    .entry(successor)
+                    .and_modify(|c| *c = c.merge(cost))
+                    .or_insert(cost);
+            }
+        }
+    }
+
+    LoopEstimate {
+        start,
+        end,
+        iteration: iteration.unwrap_or_default(),
+        exit,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_straight_line() {
+        // mov cx, 3
+        // mov cx, [bx]
+        // add [1001], cx
+        let report = estimate_program(&[0xB9, 0x03, 0x00, 0x8B, 0x0F, 0x01, 0x0E, 0xE9, 0x03]);
+
+        assert_eq!(report.blocks.len(), 1);
+        assert!(report.loops.is_empty());
+
+        // 4, then 8+5ea (+4 on an odd bx), then 16+6ea+8 (odd direct address, two transfers).
+        let block = &report.blocks[0];
+        assert_eq!(
+            block.body,
+            CostRange {
+                best: 47,
+                worst: 51
+            }
+        );
+        assert_eq!(block.jump, None);
+        assert_eq!(report.to_string(), "0000..0009: 47-51 cycles\n");
+    }
+
+    #[test]
+    fn test_loop() {
+        // mov cx, 3
+        // top:
+        // sub cx, 1
+        // jnz top
+        // mov dx, 1
+        let bytes = [
+            0xB9, 0x03, 0x00, 0x83, 0xE9, 0x01, 0x75, 0xFB, 0xBA, 0x01, 0x00,
+        ];
+        let report = estimate_program(&bytes);
+
+        assert_eq!(
+            report.loops,
+            vec![LoopEstimate {
+                start: 3,
+                end: 8,
+                iteration: CostRange::exact(4 + 16),
+                exit: Some(CostRange::exact(4 + 4)),
+            }]
+        );
+        assert_eq!(
+            report.to_string(),
+            "0000..0003: 4 cycles\n\
+             0003..0008: 4 cycles + jump (16 taken, 4 not taken)\n\
+             0008..000B: 4 cycles\n\
+             loop 0003..0008: 20 cycles per iteration, 8 cycles on the last one\n"
+        );
+    }
+
+    #[test]
+    fn test_branch_inside_loop() {
+        // top:
+        // cmp ax, bx
+        // je skip
+        // add ax, [bx + si]
+        // skip:
+        // loop top
+        let bytes = [0x39, 0xD8, 0x74, 0x02, 0x03, 0x00, 0xE2, 0xF8];
+        let report = estimate_program(&bytes);
+
+        // cmp (3) + je taken (16) + loop (17) at best.
+        // cmp (3) + je not taken (4) + add (9+7ea, +4 odd) + loop (17) at worst.
+        assert_eq!(
+            report.loops,
+            vec![LoopEstimate {
+                start: 0,
+                end: 8,
+                iteration: CostRange {
+                    best: 36,
+                    worst: 44
+                },
+                exit: Some(CostRange {
+                    best: 24,
+                    worst: 32
+                }),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unconditional_jump() {
+        // mov cx, 3 / jmp over / mov bx, cx
+        let report = estimate_program(&[0xB9, 0x03, 0x00, 0xEB, 0x00, 0x89, 0xCB]);
+        assert_eq!(report.blocks.len(), 2);
+        assert_eq!(report.blocks[0].jump, Some((15, 15)));
+        assert!(report.blocks.iter().all(|b| b.unknown.is_empty()));
+    }
+}
@@ -0,0 +1,269 @@
+use super::cpu::*;
+use super::error::IntelError;
+use super::memory;
+use super::registers::*;
+use super::SimulationResult;
+use log::debug;
+
+// What a simulation should end with, in the syntax of the "; ANSWER" block of the listings:
+//
+// ; ANSWER
+// ; bx: 0x03e8 (1000)
+// ; flags: ZS
+// ; [1000]: 01 00 02 00
+// ; cycles: 192
+// ; memory hash: 0x8a3c9f1b2e4d5a60
+//
+// Registers and flags that are not listed are expected to be zero. Cycles and memory are only
+// checked when listed.
+#[derive(Debug)]
+pub struct Expected {
+    pub cpu: CPU,
+    pub cycles: Option<usize>,
+    // "; [1000]: 01 00 02 00" -> (1000, [1, 0, 2, 0])
+    pub memory: Vec<(usize, Vec<u8>)>,
+    // The |memory::hash| of the whole memory.
+    pub memory_hash: Option<u64>,
+}
+
+// The order in which the registers are shown in a diff.
+const DIFF_REGISTERS: [&str; 13] = [
+    "ax", "bx", "cx", "dx", "sp", "bp", "si", "di", "ip", "es", "cs", "ss", "ds",
+];
+
+// The lines of the "; ANSWER" block of a listing. Empty if it has none.
+pub fn answer_lines(listing: &str) -> impl Iterator<Item = &str> {
+    listing
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| *line != "; ANSWER")
+        .skip(1)
+}
+
+pub fn parse_answer(listing: &str) -> Result<Expected, IntelError> {
+    parse_expectations(answer_lines(listing))
+}
+
+// Parses "; name: value" lines. Anything else is ignored.
+pub fn parse_expectations<'a>(
+    lines: impl IntoIterator<Item = &'a str>,
+) -> Result<Expected, IntelError> {
+    let mut want = Expected {
+        cpu: CPU::new(),
+        cycles: None,
+        memory: vec![],
+        memory_hash: None,
+    };
+
+    for line in lines {
+        let Some((pattern, value)) = line
+            .trim()
+            .strip_prefix("; ")
+            .and_then(|l| l.split_once(':'))
+        else {
+            continue;
+        };
+        let pattern = pattern.trim();
+        let value = value.trim();
+
+        if let Some(reg) = Register::find(pattern) {
+            let regval = parse_hex_u16(value)?;
+            debug!("setting test register {0} to 0x{1:04X} ({1})", reg, regval);
+            want.cpu.set_register(&reg, regval);
+            continue;
+        }
+
+        if let Some(address) = pattern.strip_prefix('[').and_then(|p| p.strip_suffix(']')) {
+            want.memory
+                .push((parse_address(address)?, parse_bytes(value)?));
+            continue;
+        }
+
+        match pattern {
+            "flags" => parse_flags(&mut want.cpu, value),
+            "cycles" => {
+                let cycles = value
+                    .parse()
+                    .map_err(|e| invalid(format!("cycles {}: {}", value, e)))?;
+                want.cycles = Some(cycles);
+            }
+            "memory hash" => {
+                let hash = value
+                    .strip_prefix("0x")
+                    .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| invalid(format!("expected a 0x hash: {}", value)))?;
+                want.memory_hash = Some(hash);
+            }
+            _ => return Err(invalid(format!("unknown pattern {}", pattern))),
+        }
+    }
+
+    debug!("Parsed CPU: {:?}", want.cpu);
+    Ok(want)
+}
+
+// Returns one line for everything in |result| that is not what |want| expects.
+pub fn check(want: &Expected, result: &SimulationResult) -> Vec<String> {
+    let got = &result.cpu;
+    let mut mismatches = vec![];
+
+    for name in DIFF_REGISTERS {
+        let register = Register::find(name).unwrap();
+        let want_value = want.cpu.get_register(&register);
+        let got_value = got.get_register(&register);
+        if want_value != got_value {
+            mismatches.push(format!(
+                "{}: want {} got {}",
+                name,
+                printu16(want_value),
+                printu16(got_value)
+            ));
+        }
+    }
+
+    if want.cpu.flags != got.flags {
+        mismatches.push(format!(
+            "flags: want \"{}\" got \"{}\"",
+            want.cpu.print_flags(),
+            got.print_flags()
+        ));
+    }
+
+    for (address, want_bytes) in &want.memory {
+        let Some(got_bytes) = got
+            .get_memory()
+            .get(*address..(*address + want_bytes.len()))
+        else {
+            mismatches.push(format!("memory: address {} out of memory", address));
+            continue;
+        };
+
+        if want_bytes.as_slice() != got_bytes {
+            for change in memory::diff(*address, want_bytes, got_bytes) {
+                mismatches.push(format!("memory (want -> got): {}", change));
+            }
+
+            let dump = memory::hexdump(got.get_memory(), *address, want_bytes.len());
+            mismatches.push(format!("got:\n    {}", dump.trim_end().replace('\n', "\n    ")));
+        }
+    }
+
+    if let Some(cycles) = want.cycles {
+        if cycles != result.cycles {
+            mismatches.push(format!("cycles: want {} got {}", cycles, result.cycles));
+        }
+    }
+
+    if let Some(hash) = want.memory_hash {
+        let got_hash = memory::hash(got.get_memory());
+        if hash != got_hash {
+            mismatches.push(format!("memory hash: want 0x{:016x} got 0x{:016x}", hash, got_hash));
+        }
+    }
+
+    mismatches
+}
+
+// HELPERS -----------------------------------------------------------------------------------------
+
+fn invalid(message: String) -> IntelError {
+    IntelError::InvalidExpectation(message)
+}
+
+fn parse_flags(cpu: &mut CPU, pattern: &str) {
+    for c in pattern.chars() {
+        match c {
+            'Z' => cpu.flags.z = true,
+            'S' => cpu.flags.s = true,
+            'O' => cpu.flags.o = true,
+            'I' => cpu.flags.i = true,
+            'T' => cpu.flags.t = true,
+            _ => {}
+        }
+    }
+}
+
+// "0xNNNN (N)" -> NNNN
+fn parse_hex_u16(value: &str) -> Result<u16, IntelError> {
+    value
+        .split_whitespace()
+        .next()
+        .and_then(|v| v.strip_prefix("0x"))
+        .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+        .ok_or_else(|| invalid(format!("expected a 0x value: {}", value)))
+}
+
+// Same notation as nasm: decimal, or hex with a 0x prefix.
+fn parse_address(address: &str) -> Result<usize, IntelError> {
+    let address = address.trim();
+    let result = match address.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => address.parse(),
+    };
+    result.map_err(|e| invalid(format!("{}: {}", address, e)))
+}
+
+// "01 00 02 00" -> [1, 0, 2, 0]
+fn parse_bytes(value: &str) -> Result<Vec<u8>, IntelError> {
+    value
+        .split_whitespace()
+        .map(|b| u8::from_str_radix(b, 16).map_err(|e| invalid(format!("{}: {}", b, e))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intel8086::simulate;
+
+    #[test]
+    fn test_parse_answer() {
+        let listing = "mov cx, 3\n\
+                       ; cx: 0x0001\n\
+                       ; ANSWER\n\
+                       ; cx: 0x0003 (3)\n\
+                       ; flags: ZO\n\
+                       ; [0x10]: 01 ff\n\
+                       ; cycles: 4\n";
+
+        let want = parse_answer(listing).unwrap();
+        assert_eq!(want.cpu.cx(), 3);
+        assert!(want.cpu.flags.z && want.cpu.flags.o && !want.cpu.flags.s);
+        assert_eq!(want.memory, vec![(0x10, vec![0x01, 0xFF])]);
+        assert_eq!(want.cycles, Some(4));
+        assert_eq!(want.memory_hash, None);
+
+        assert!(matches!(
+            parse_expectations(["; nope: 1"]),
+            Err(IntelError::InvalidExpectation(_))
+        ));
+    }
+
+    #[test]
+    fn test_check() {
+        // mov cx, 3
+        let result = simulate(&[0xB9, 0x03, 0x00]).unwrap();
+        let hash = memory::hash(result.cpu.get_memory());
+
+        let lines = [
+            "; cx: 0x0003".to_string(),
+            "; ip: 0x0003".to_string(),
+            "; cycles: 4".to_string(),
+            format!("; memory hash: 0x{:016x}", hash),
+        ];
+        let want = parse_expectations(lines.iter().map(String::as_str)).unwrap();
+        assert!(check(&want, &result).is_empty());
+
+        let want = parse_expectations(["; cx: 0x0004", "; [0]: b9 04", "; cycles: 5"]).unwrap();
+        assert_eq!(
+            check(&want, &result),
+            vec![
+                "cx: want 0x0004 (4) got 0x0003 (3)",
+                "ip: want 0x0000 (0) got 0x0003 (3)",
+                "memory (want -> got): 0x00001 (1 bytes): 04 -> 03",
+                "got:\n    0x00000  b9 03                                             |..              |",
+                "cycles: want 5 got 4",
+            ]
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use super::instructions::*;
+use super::labels::*;
+use super::listing::*;
+use super::syntax::*;
+use super::traversal::*;
+use super::*;
+
+// Invariants the decoder must hold for any byte stream. Panics when one is broken, so it can be
+// called both by the fuzz target (fuzz/) and by the randomized tests.
+pub fn check_decoder_invariants(bytes: &[u8]) {
+    let result = disassemble_recovering(bytes);
+
+    // Every byte ends up either in an instruction or in a data block.
+    let code: usize = result.instructions.iter().map(Instruction::len).sum();
+    let data: usize = result.data.iter().map(|d| d.bytes.len()).sum();
+    assert_eq!(code + data, bytes.len(), "{:02X?}", bytes);
+
+    // The strict disassembler agrees with the tolerant one when there is nothing to recover from.
+    match disassemble(bytes) {
+        Ok(instructions) => assert_eq!(instructions, result.instructions, "{:02X?}", bytes),
+        Err(_) => assert!(!result.diagnostics.is_empty(), "{:02X?}", bytes),
+    }
+
+    // Whatever decodes must re-encode into something that decodes to the same text.
+    for instruction in &result.instructions {
+        let encoded = instruction
+            .encode()
+            .unwrap_or_else(|e| panic!("{}: cannot encode: {}", instruction, e));
+        let decoded = Instruction::decode(&encoded)
+            .unwrap_or_else(|e| panic!("{}: cannot decode {:02X?}: {}", instruction, encoded, e));
+        assert_eq!(decoded.len(), encoded.len(), "{}", instruction);
+        assert_eq!(decoded.to_string(), instruction.to_string(), "{:02X?}", encoded);
+    }
+
+    // None of the printers can choke on what the decoder produced.
+    let formatters: [&dyn SyntaxFormatter; 3] = [&Nasm, &Masm, &Att];
+    for formatter in formatters {
+        to_asm_with_labels(&result.instructions, formatter);
+        to_asm_with_data(&result.instructions, &result.data, formatter);
+        to_listing(0, &result.instructions, &result.data, formatter);
+    }
+
+    let traversal = disassemble_recursive(bytes, &[0]);
+    let code: usize = traversal.instructions.iter().map(Instruction::len).sum();
+    let data: usize = traversal.data.iter().map(|d| d.bytes.len()).sum();
+    assert_eq!(code + data, bytes.len(), "{:02X?}", bytes);
+}
@@ -1,10 +1,12 @@
 use super::decoding::*;
+use super::encoding::*;
 use super::error::*;
 use super::registers::*;
 use bitfield_struct::bitfield;
 use log::debug;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Instruction {
     pub address: usize,
     pub data: [u8; 6], // Instructions are at most 6 bytes.
@@ -23,6 +25,7 @@ pub struct Instruction {
 }
 
 #[bitfield(u16)]
+#[derive(PartialEq, Eq)]
 pub struct InstructionBits {
     #[bits(2)]
     pub vmod: u8,
@@ -65,6 +68,13 @@ impl Instruction {
             return decode_mov_immediate_to_register(bytes);
         }
 
+        // Segment register to/from register/memory.
+        if peek == 0x8E {
+            return decode_mov_segment(bytes, true);
+        } else if peek == 0x8C {
+            return decode_mov_segment(bytes, false);
+        }
+
         // Immediate register to/from_memory.
         if compare_mask(peek, 0b1100011, 7) {
             return decode_immediate_to_register_memory(bytes, Operation::Mov);
@@ -85,6 +95,33 @@ impl Instruction {
             return decode_op_immediate_to_accumulator(bytes, Operation::Cmp);
         }
 
+        // Input/Output, with the port as an immediate or in dx.
+        if compare_mask(peek, 0b1110010, 7) {
+            return decode_in_out(bytes, Operation::In, false);
+        } else if compare_mask(peek, 0b1110110, 7) {
+            return decode_in_out(bytes, Operation::In, true);
+        } else if compare_mask(peek, 0b1110011, 7) {
+            return decode_in_out(bytes, Operation::Out, false);
+        } else if compare_mask(peek, 0b1110111, 7) {
+            return decode_in_out(bytes, Operation::Out, true);
+        }
+
+        // Divisions (the rest of the 1111011w group is not supported).
+        if compare_mask(peek, 0b1111011, 7) {
+            return decode_division(bytes);
+        }
+
+        // Interrupts and the interrupt flag.
+        match peek {
+            0xCD => return decode_interrupt(bytes),
+            0xCC => return decode_no_operands(bytes, Operation::Int3),
+            0xCE => return decode_no_operands(bytes, Operation::Into),
+            0xCF => return decode_no_operands(bytes, Operation::Iret),
+            0xFA => return decode_no_operands(bytes, Operation::Cli),
+            0xFB => return decode_no_operands(bytes, Operation::Sti),
+            _ => {}
+        }
+
         // Jumps
         for jump in SHORT_JUMPS {
             if peek == jump.opcode {
@@ -102,6 +139,12 @@ impl Instruction {
         Err(IntelError::UnsupportedOpcode(peek))
     }
 
+    // Returns the canonical byte sequence for this instruction, picking the shortest form the
+    // same way NASM does (short displacements, sign-extended immediates, accumulator forms).
+    pub fn encode(&self) -> Result<Vec<u8>, IntelError> {
+        encode_instruction(self)
+    }
+
     pub(super) fn new() -> Self {
         Instruction {
             ..Default::default()
@@ -112,6 +155,21 @@ impl Instruction {
         self.len as usize
     }
 
+    // Returns the absolute address a jump lands on, if this is a jump.
+    // The offset is relative to the end of the instruction.
+    pub fn jump_target(&self) -> Option<usize> {
+        let Operand::JumpOffset(offset) = self.src else {
+            return None;
+        };
+
+        let target = (self.address + self.len()) as isize + offset as isize;
+        if target < 0 {
+            return None;
+        }
+
+        Some(target as usize)
+    }
+
     pub(super) fn consume(&mut self, bytes: &[u8], amount: usize) -> Result<(), IntelError> {
         let start = self.len();
         let end = self.len() + amount;
@@ -151,6 +209,16 @@ pub enum Operation {
     Sub,
     Cmp,
     Jump(JumpDescription),
+    In,
+    Out,
+    Div,
+    Idiv,
+    Int,
+    Int3,
+    Into,
+    Iret,
+    Cli,
+    Sti,
 }
 
 pub enum CPUFlag {
@@ -159,11 +227,13 @@ pub enum CPUFlag {
     Sign,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
 pub enum Operand {
     Invalid,
     Register(Register),
     Immediate(u16),
+    #[serde(rename = "eac")]
     EAC(EAC),
     JumpOffset(i8),
 }
@@ -186,7 +256,16 @@ impl Operand {
 
 impl std::fmt::Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.dst.is_valid() && !self.src.is_valid() {
+            return write!(f, "{}", self.operation);
+        }
+
         if !self.src.is_valid() {
+            // A lone memory operand doesn't tell its size.
+            if let Operand::EAC(_) = self.dst {
+                let size_specifier = if self.bits.w() { "word" } else { "byte" };
+                return write!(f, "{} {} {}", self.operation, size_specifier, self.dst);
+            }
             return write!(f, "{} {}", self.operation, self.dst);
         }
 
@@ -213,12 +292,42 @@ impl std::fmt::Display for Operation {
             Operation::Sub => "sub",
             Operation::Cmp => "cmp",
             Operation::Jump(jump) => jump.name,
+            Operation::In => "in",
+            Operation::Out => "out",
+            Operation::Div => "div",
+            Operation::Idiv => "idiv",
+            Operation::Int => "int",
+            Operation::Int3 => "int3",
+            Operation::Into => "into",
+            Operation::Iret => "iret",
+            Operation::Cli => "cli",
+            Operation::Sti => "sti",
         };
 
         write!(f, "{}", string)
     }
 }
 
+// We only serialize what describes the instruction. The decoding bits are an implementation detail.
+impl Serialize for Instruction {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Instruction", 6)?;
+        state.serialize_field("address", &self.address)?;
+        state.serialize_field("bytes", &self.data[..self.len()])?;
+        state.serialize_field("operation", &self.operation)?;
+        state.serialize_field("wide", &is_wide(self).unwrap_or(false))?;
+        state.serialize_field("dst", &self.dst)?;
+        state.serialize_field("src", &self.src)?;
+        state.end()
+    }
+}
+
+impl Serialize for Operation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
 impl std::fmt::Display for Operand {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -228,7 +337,7 @@ impl std::fmt::Display for Operand {
             Operand::EAC(eac) => write!(f, "{}", eac),
             Operand::JumpOffset(offset) => {
                 // The jump encoding has a 2 implicit offset.
-                let offset = offset + 2;
+                let offset = *offset as i16 + 2;
                 if offset > 0 {
                     write!(f, "$+{}+0", offset)
                 } else if offset == 0 {
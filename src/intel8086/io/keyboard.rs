@@ -0,0 +1,103 @@
+use super::Device;
+use log::debug;
+use std::collections::VecDeque;
+
+pub const KEYBOARD_DATA_PORT: u16 = 0x60;
+pub const KEYBOARD_STATUS_PORT: u16 = 0x64;
+
+// Status bits.
+const OUTPUT_BUFFER_FULL: u8 = 0b0000_0001;
+const SYSTEM_FLAG: u8 = 0b0000_0100;
+
+// Stand-in for the keyboard controller. Instead of a keyboard it hands out a scripted list of
+// scancodes, one per read of the data port, so runs are reproducible.
+#[derive(Debug, Default)]
+pub struct Keyboard {
+    scancodes: VecDeque<u8>,
+    // The data port keeps returning the last scancode once the queue is empty.
+    last: u8,
+    // Whether IRQ 1 was raised for the scancode at the front of the queue.
+    signaled: bool,
+}
+
+impl Keyboard {
+    pub fn new(scancodes: impl IntoIterator<Item = u8>) -> Self {
+        Keyboard {
+            scancodes: scancodes.into_iter().collect(),
+            last: 0,
+            signaled: false,
+        }
+    }
+
+    pub fn push(&mut self, scancode: u8) {
+        self.scancodes.push_back(scancode);
+    }
+
+    // Whether a scancode is waiting to be read. On a PC this raises IRQ 1.
+    pub fn has_data(&self) -> bool {
+        !self.scancodes.is_empty()
+    }
+}
+
+impl Device for Keyboard {
+    fn name(&self) -> &'static str {
+        "keyboard"
+    }
+
+    fn handles(&self, port: u16) -> bool {
+        port == KEYBOARD_DATA_PORT || port == KEYBOARD_STATUS_PORT
+    }
+
+    fn read(&mut self, port: u16) -> u8 {
+        if port == KEYBOARD_STATUS_PORT {
+            let full = if self.has_data() {
+                OUTPUT_BUFFER_FULL
+            } else {
+                0
+            };
+            return SYSTEM_FLAG | full;
+        }
+
+        if let Some(scancode) = self.scancodes.pop_front() {
+            self.last = scancode;
+            self.signaled = false;
+        }
+        self.last
+    }
+
+    fn write(&mut self, port: u16, value: u8) {
+        // Controller and keyboard commands (LEDs, typematic rate...) have no effect here.
+        debug!("keyboard: ignoring command 0x{:02X} on port 0x{:02X}", value, port);
+    }
+
+    fn take_irqs(&mut self) -> u8 {
+        if !self.has_data() || self.signaled {
+            return 0;
+        }
+
+        self.signaled = true;
+        0b10
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripted_scancodes() {
+        // "a" pressed and released.
+        let mut keyboard = Keyboard::new([0x1E, 0x9E]);
+
+        assert_eq!(keyboard.read(KEYBOARD_STATUS_PORT), SYSTEM_FLAG | OUTPUT_BUFFER_FULL);
+        assert_eq!(keyboard.read(KEYBOARD_DATA_PORT), 0x1E);
+        assert_eq!(keyboard.read(KEYBOARD_DATA_PORT), 0x9E);
+
+        assert_eq!(keyboard.read(KEYBOARD_STATUS_PORT), SYSTEM_FLAG);
+        assert_eq!(keyboard.read(KEYBOARD_DATA_PORT), 0x9E);
+
+        keyboard.push(0x1F);
+        assert!(keyboard.has_data());
+        assert_eq!(keyboard.read(KEYBOARD_DATA_PORT), 0x1F);
+    }
+}
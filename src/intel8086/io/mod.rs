@@ -0,0 +1,160 @@
+mod keyboard;
+mod pic;
+mod pit;
+
+pub use keyboard::*;
+pub use pic::*;
+pub use pit::*;
+
+use log::debug;
+
+// Something on the other side of the in/out instructions.
+pub trait Device {
+    fn name(&self) -> &'static str;
+
+    // Whether this device answers to |port|.
+    fn handles(&self, port: u16) -> bool;
+
+    fn read(&mut self, port: u16) -> u8;
+    fn write(&mut self, port: u16, value: u8);
+
+    // Called after every instruction with the cycles it took, so devices with a clock can advance
+    // in lockstep with the CPU.
+    fn tick(&mut self, _cycles: usize) {}
+
+    // Interrupt requests raised since the last call, bit N for IRQ N.
+    fn take_irqs(&mut self) -> u8 {
+        0
+    }
+
+    // Only for the interrupt controller: an IRQ line went up, and the CPU takes the interrupt.
+    fn raise_irq(&mut self, _irq: u8) {}
+    fn acknowledge_interrupt(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+// What an 8086 reads from a port nobody answers to.
+const FLOATING_BUS: u8 = 0xFF;
+
+// Routes port accesses to the attached devices. If more than one device handles a port, the first
+// one attached wins.
+#[derive(Default)]
+pub struct IoBus {
+    devices: Vec<Box<dyn Device>>,
+}
+
+impl IoBus {
+    // The PIT, PIC and keyboard controller of a PC. The keyboard hands out |scancodes| in order.
+    pub fn pc(scancodes: impl IntoIterator<Item = u8>) -> Self {
+        let mut bus = IoBus::default();
+        bus.attach(Box::new(Pit::new()));
+        bus.attach(Box::new(Pic::new()));
+        bus.attach(Box::new(Keyboard::new(scancodes)));
+        bus
+    }
+
+    pub fn attach(&mut self, device: Box<dyn Device>) {
+        self.devices.push(device);
+    }
+
+    pub fn read(&mut self, port: u16) -> u8 {
+        match self.device_mut(port) {
+            Some(device) => {
+                let value = device.read(port);
+                debug!("in 0x{:04X} ({}) -> 0x{:02X}", port, device.name(), value);
+                value
+            }
+            None => {
+                debug!("in 0x{:04X}: no device, reading 0x{:02X}", port, FLOATING_BUS);
+                FLOATING_BUS
+            }
+        }
+    }
+
+    pub fn write(&mut self, port: u16, value: u8) {
+        match self.device_mut(port) {
+            Some(device) => {
+                debug!("out 0x{:04X} ({}) <- 0x{:02X}", port, device.name(), value);
+                device.write(port, value);
+            }
+            None => debug!("out 0x{:04X}: no device, dropping 0x{:02X}", port, value),
+        }
+    }
+
+    pub fn tick(&mut self, cycles: usize) {
+        let mut irqs = 0;
+        for device in &mut self.devices {
+            device.tick(cycles);
+            irqs |= device.take_irqs();
+        }
+
+        for irq in (0..8).filter(|irq| irqs & (1 << irq) != 0) {
+            debug!("irq {}", irq);
+            for device in &mut self.devices {
+                device.raise_irq(irq);
+            }
+        }
+    }
+
+    // The interrupt number the CPU takes next, if the interrupt controller has one pending.
+    pub fn acknowledge_interrupt(&mut self) -> Option<u8> {
+        self.devices
+            .iter_mut()
+            .find_map(|device| device.acknowledge_interrupt())
+    }
+
+    fn device_mut(&mut self, port: u16) -> Option<&mut Box<dyn Device>> {
+        self.devices.iter_mut().find(|device| device.handles(port))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unmapped_ports() {
+        let mut bus = IoBus::pc([]);
+        assert_eq!(bus.read(0x3F8), FLOATING_BUS);
+        bus.write(0x3F8, 0x12);
+    }
+
+    #[test]
+    fn test_routes_to_devices() {
+        let mut bus = IoBus::pc([0x1E]);
+
+        // Keyboard.
+        assert_eq!(bus.read(KEYBOARD_STATUS_PORT) & 0b1, 1);
+        assert_eq!(bus.read(KEYBOARD_DATA_PORT), 0x1E);
+
+        // PIC mask register.
+        bus.write(PIC_DATA_PORT, 0xFD);
+        assert_eq!(bus.read(PIC_DATA_PORT), 0xFD);
+
+        // PIT counter 2, low byte then high byte.
+        bus.write(PIT_CONTROL_PORT, 0b1011_0100);
+        bus.write(PIT_COUNTER_PORTS[2], 0x34);
+        bus.write(PIT_COUNTER_PORTS[2], 0x12);
+        assert_eq!(bus.read(PIT_COUNTER_PORTS[2]), 0x34);
+        assert_eq!(bus.read(PIT_COUNTER_PORTS[2]), 0x12);
+    }
+
+    #[test]
+    fn test_irqs_reach_the_pic() {
+        let mut bus = IoBus::pc([0x1E]);
+
+        // The keyboard has a scancode waiting, which is IRQ 1.
+        bus.tick(1);
+        assert_eq!(bus.acknowledge_interrupt(), Some(9));
+        assert_eq!(bus.acknowledge_interrupt(), None);
+
+        // Counter 0 at 100 ticks is IRQ 0. The keyboard's IRQ is still in service, but IRQ 0
+        // goes first.
+        bus.write(PIT_CONTROL_PORT, 0b0011_0100);
+        bus.write(PIT_COUNTER_PORTS[0], 100);
+        bus.write(PIT_COUNTER_PORTS[0], 0);
+        bus.tick(400);
+        assert_eq!(bus.acknowledge_interrupt(), Some(8));
+    }
+}
@@ -0,0 +1,221 @@
+use super::Device;
+use log::debug;
+
+pub const PIC_COMMAND_PORT: u16 = 0x20;
+pub const PIC_DATA_PORT: u16 = 0x21;
+
+// Non-specific end of interrupt (OCW2).
+const EOI: u8 = 0x20;
+
+// Stand-in for a single (not cascaded) 8259A programmable interrupt controller.
+// IRQ 0 has the highest priority, and priorities never rotate.
+#[derive(Debug)]
+pub struct Pic {
+    // Interrupt number of IRQ 0. The IRQs map to |vector_base|..|vector_base| + 8.
+    vector_base: u8,
+    // Interrupt request, in service and mask registers. Bit N is IRQ N.
+    irr: u8,
+    isr: u8,
+    imr: u8,
+    init: Init,
+    // Whether reading the command port returns the ISR (true) or the IRR (false).
+    read_isr: bool,
+}
+
+// Where we are in the initialization command word sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Init {
+    Ready,
+    Icw2 { icw3: bool, icw4: bool },
+    Icw3 { icw4: bool },
+    Icw4,
+}
+
+impl Pic {
+    // Starts as the PC BIOS leaves it: IRQs on interrupts 8 to 15, nothing masked.
+    pub fn new() -> Self {
+        Pic {
+            vector_base: 8,
+            irr: 0,
+            isr: 0,
+            imr: 0,
+            init: Init::Ready,
+            read_isr: false,
+        }
+    }
+
+    pub fn raise(&mut self, irq: u8) {
+        self.irr |= 1 << irq;
+    }
+
+    // The interrupt number the CPU should take, if any IRQ is requested, unmasked and of higher
+    // priority than the ones in service.
+    pub fn pending(&self) -> Option<u8> {
+        let irq = self.pending_irq()?;
+        Some(self.vector_base + irq)
+    }
+
+    // The CPU takes the pending interrupt: it moves from requested to in service until the EOI.
+    pub fn acknowledge(&mut self) -> Option<u8> {
+        let irq = self.pending_irq()?;
+        self.irr &= !(1 << irq);
+        self.isr |= 1 << irq;
+        Some(self.vector_base + irq)
+    }
+
+    fn pending_irq(&self) -> Option<u8> {
+        let requested = self.irr & !self.imr;
+        if requested == 0 {
+            return None;
+        }
+
+        let irq = requested.trailing_zeros() as u8;
+
+        // Anything in service with the same or higher priority blocks it.
+        let blocking = self.isr & ((1u16 << (irq + 1)) - 1) as u8;
+        if blocking != 0 {
+            return None;
+        }
+        Some(irq)
+    }
+
+    fn command(&mut self, value: u8) {
+        if value & 0b1_0000 != 0 {
+            // ICW1 restarts the initialization.
+            self.irr = 0;
+            self.isr = 0;
+            self.imr = 0;
+            self.read_isr = false;
+            self.init = Init::Icw2 {
+                icw3: value & 0b10 == 0,
+                icw4: value & 0b01 != 0,
+            };
+            return;
+        }
+
+        if value & 0b1000 != 0 {
+            // OCW3. Only the register read selection is supported.
+            if value & 0b10 != 0 {
+                self.read_isr = value & 0b01 != 0;
+            }
+            return;
+        }
+
+        // OCW2.
+        match value & 0b1110_0000 {
+            EOI => {
+                if self.isr != 0 {
+                    let irq = self.isr.trailing_zeros();
+                    self.isr &= !(1 << irq);
+                }
+            }
+            0b0110_0000 => self.isr &= !(1 << (value & 0b111)),
+            _ => debug!("pic: unsupported OCW2 0x{:02X}", value),
+        }
+    }
+
+    fn data(&mut self, value: u8) {
+        self.init = match self.init {
+            Init::Ready => {
+                self.imr = value;
+                Init::Ready
+            }
+            Init::Icw2 { icw3, icw4 } => {
+                self.vector_base = value & 0b1111_1000;
+                match (icw3, icw4) {
+                    (true, _) => Init::Icw3 { icw4 },
+                    (false, true) => Init::Icw4,
+                    (false, false) => Init::Ready,
+                }
+            }
+            // We don't cascade, so the slave configuration is ignored.
+            Init::Icw3 { icw4: true } => Init::Icw4,
+            Init::Icw3 { icw4: false } => Init::Ready,
+            Init::Icw4 => Init::Ready,
+        }
+    }
+}
+
+impl Default for Pic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Pic {
+    fn name(&self) -> &'static str {
+        "pic"
+    }
+
+    fn handles(&self, port: u16) -> bool {
+        port == PIC_COMMAND_PORT || port == PIC_DATA_PORT
+    }
+
+    fn read(&mut self, port: u16) -> u8 {
+        match port {
+            PIC_DATA_PORT => self.imr,
+            _ if self.read_isr => self.isr,
+            _ => self.irr,
+        }
+    }
+
+    fn write(&mut self, port: u16, value: u8) {
+        match port {
+            PIC_DATA_PORT => self.data(value),
+            _ => self.command(value),
+        }
+    }
+
+    fn raise_irq(&mut self, irq: u8) {
+        self.raise(irq);
+    }
+
+    fn acknowledge_interrupt(&mut self) -> Option<u8> {
+        self.acknowledge()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_initialization() {
+        let mut pic = Pic::new();
+        // ICW1: single, ICW4 needed. ICW2: vectors at 0x50. ICW4: 8086 mode.
+        pic.write(PIC_COMMAND_PORT, 0x13);
+        pic.write(PIC_DATA_PORT, 0x50);
+        pic.write(PIC_DATA_PORT, 0x01);
+        // OCW1: mask everything but IRQ 1.
+        pic.write(PIC_DATA_PORT, 0xFD);
+        assert_eq!(pic.read(PIC_DATA_PORT), 0xFD);
+
+        pic.raise(0);
+        assert_eq!(pic.pending(), None);
+        pic.raise(1);
+        assert_eq!(pic.pending(), Some(0x51));
+    }
+
+    #[test]
+    fn test_priorities_and_eoi() {
+        let mut pic = Pic::new();
+        pic.raise(3);
+        assert_eq!(pic.acknowledge(), Some(11));
+
+        // Lower priority waits, higher priority nests.
+        pic.raise(5);
+        assert_eq!(pic.pending(), None);
+        pic.raise(0);
+        assert_eq!(pic.acknowledge(), Some(8));
+
+        // Reading the ISR through OCW3.
+        pic.write(PIC_COMMAND_PORT, 0x0B);
+        assert_eq!(pic.read(PIC_COMMAND_PORT), 0b1001);
+
+        // Non-specific EOI ends the highest priority one first.
+        pic.write(PIC_COMMAND_PORT, EOI);
+        assert_eq!(pic.read(PIC_COMMAND_PORT), 0b1000);
+        pic.write(PIC_COMMAND_PORT, 0x63);
+        assert_eq!(pic.acknowledge(), Some(13));
+    }
+}
@@ -0,0 +1,269 @@
+use super::Device;
+
+pub const PIT_COUNTER_PORTS: [u16; 3] = [0x40, 0x41, 0x42];
+pub const PIT_CONTROL_PORT: u16 = 0x43;
+
+// The PIT runs at 1.193182 MHz and the CPU at 4.77 MHz.
+const CPU_CYCLES_PER_PIT_TICK: usize = 4;
+
+// Stand-in for the 8253 programmable interval timer.
+// Counters in mode 2 or 3 reload when they reach zero, the rest keep counting down from 0xFFFF.
+// Mode 3 counts by one like mode 2, and BCD counting is not supported.
+#[derive(Debug, Default)]
+pub struct Pit {
+    counters: [Counter; 3],
+    // CPU cycles that haven't made a full PIT tick yet.
+    pending_cycles: usize,
+    // Expirations of counter 0 already reported as IRQ 0.
+    reported: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    #[default]
+    LowHigh,
+    Low,
+    High,
+}
+
+#[derive(Debug, Default)]
+struct Counter {
+    access: Access,
+    mode: u8,
+    reload: u16,
+    count: u16,
+    // Counting only starts once the whole reload value has been written.
+    running: bool,
+    latched: Option<u16>,
+    // For LowHigh access: whether the next read/write is the high byte.
+    read_high: bool,
+    write_high: bool,
+    // How many times the count went through zero.
+    expirations: usize,
+}
+
+impl Pit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // How many times counter |index| reached zero so far. Counter 0 drives IRQ 0 on a PC.
+    pub fn expirations(&self, index: usize) -> usize {
+        self.counters[index].expirations
+    }
+
+    fn control(&mut self, value: u8) {
+        let index = (value >> 6) as usize;
+        let Some(counter) = self.counters.get_mut(index) else {
+            // 0b11 is the read-back command of the 8254, which the 8253 doesn't have.
+            return;
+        };
+
+        let access = match (value >> 4) & 0b11 {
+            0b00 => {
+                // Latch the current count. A second latch before reading is ignored.
+                counter.latched.get_or_insert(counter.count);
+                return;
+            }
+            0b01 => Access::Low,
+            0b10 => Access::High,
+            _ => Access::LowHigh,
+        };
+
+        *counter = Counter {
+            access,
+            mode: (value >> 1) & 0b111,
+            expirations: counter.expirations,
+            ..Default::default()
+        };
+    }
+}
+
+impl Counter {
+    fn write(&mut self, value: u8) {
+        match self.access {
+            Access::Low => self.load(value as u16),
+            Access::High => self.load((value as u16) << 8),
+            Access::LowHigh if !self.write_high => {
+                self.reload = value as u16;
+                self.write_high = true;
+                // Writing the first byte stops the count until the second one arrives.
+                self.running = false;
+            }
+            Access::LowHigh => {
+                self.write_high = false;
+                self.load((self.reload & 0x00FF) | ((value as u16) << 8));
+            }
+        }
+    }
+
+    fn load(&mut self, reload: u16) {
+        self.reload = reload;
+        self.count = reload;
+        self.running = true;
+    }
+
+    fn read(&mut self) -> u8 {
+        let value = self.latched.unwrap_or(self.count);
+
+        let (byte, done) = match self.access {
+            Access::Low => (value as u8, true),
+            Access::High => ((value >> 8) as u8, true),
+            Access::LowHigh if !self.read_high => {
+                self.read_high = true;
+                (value as u8, false)
+            }
+            Access::LowHigh => {
+                self.read_high = false;
+                ((value >> 8) as u8, true)
+            }
+        };
+
+        if done {
+            self.latched = None;
+        }
+        byte
+    }
+
+    fn advance(&mut self, ticks: usize) {
+        if !self.running || ticks == 0 {
+            return;
+        }
+
+        // A count of 0 stands for 65536.
+        let count = if self.count == 0 {
+            0x10000
+        } else {
+            self.count as usize
+        };
+        if ticks < count {
+            self.count = (count - ticks) as u16;
+            return;
+        }
+
+        let period = match self.mode {
+            0b010 | 0b110 | 0b011 | 0b111 if self.reload != 0 => self.reload as usize,
+            _ => 0x10000,
+        };
+
+        let overshoot = ticks - count;
+        self.expirations += 1 + overshoot / period;
+        self.count = ((period - overshoot % period) % 0x10000) as u16;
+    }
+}
+
+impl Device for Pit {
+    fn name(&self) -> &'static str {
+        "pit"
+    }
+
+    fn handles(&self, port: u16) -> bool {
+        PIT_COUNTER_PORTS.contains(&port) || port == PIT_CONTROL_PORT
+    }
+
+    fn read(&mut self, port: u16) -> u8 {
+        match PIT_COUNTER_PORTS.iter().position(|p| *p == port) {
+            Some(index) => self.counters[index].read(),
+            // The control word cannot be read back.
+            None => 0xFF,
+        }
+    }
+
+    fn write(&mut self, port: u16, value: u8) {
+        match PIT_COUNTER_PORTS.iter().position(|p| *p == port) {
+            Some(index) => self.counters[index].write(value),
+            None => self.control(value),
+        }
+    }
+
+    fn tick(&mut self, cycles: usize) {
+        self.pending_cycles += cycles;
+        let ticks = self.pending_cycles / CPU_CYCLES_PER_PIT_TICK;
+        self.pending_cycles %= CPU_CYCLES_PER_PIT_TICK;
+
+        for counter in &mut self.counters {
+            counter.advance(ticks);
+        }
+    }
+
+    fn take_irqs(&mut self) -> u8 {
+        let expirations = self.counters[0].expirations;
+        if expirations == self.reported {
+            return 0;
+        }
+
+        self.reported = expirations;
+        0b1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counter 0, low then high byte, mode 2.
+    const RATE_GENERATOR: u8 = 0b0011_0100;
+
+    fn read_count(pit: &mut Pit, index: usize) -> u16 {
+        let low = pit.read(PIT_COUNTER_PORTS[index]) as u16;
+        let high = pit.read(PIT_COUNTER_PORTS[index]) as u16;
+        low | (high << 8)
+    }
+
+    #[test]
+    fn test_counts_down_and_reloads() {
+        let mut pit = Pit::new();
+        pit.write(PIT_CONTROL_PORT, RATE_GENERATOR);
+        pit.write(PIT_COUNTER_PORTS[0], 100);
+        pit.write(PIT_COUNTER_PORTS[0], 0);
+
+        pit.tick(40);
+        assert_eq!(read_count(&mut pit, 0), 90);
+
+        // 90 more ticks reach zero, the next 5 come from the reloaded count.
+        pit.tick(95 * CPU_CYCLES_PER_PIT_TICK);
+        assert_eq!(read_count(&mut pit, 0), 95);
+        assert_eq!(pit.expirations(0), 1);
+
+        pit.tick(1000 * CPU_CYCLES_PER_PIT_TICK);
+        assert_eq!(pit.expirations(0), 11);
+    }
+
+    #[test]
+    fn test_waits_for_full_reload() {
+        let mut pit = Pit::new();
+        pit.write(PIT_CONTROL_PORT, RATE_GENERATOR);
+        pit.write(PIT_COUNTER_PORTS[0], 100);
+
+        pit.tick(40);
+        assert_eq!(pit.expirations(0), 0);
+        assert_eq!(read_count(&mut pit, 0), 0);
+    }
+
+    #[test]
+    fn test_latch() {
+        let mut pit = Pit::new();
+        pit.write(PIT_CONTROL_PORT, RATE_GENERATOR);
+        pit.write(PIT_COUNTER_PORTS[0], 0x00);
+        pit.write(PIT_COUNTER_PORTS[0], 0x10);
+
+        pit.write(PIT_CONTROL_PORT, 0b0000_0000);
+        pit.tick(16);
+
+        // The latched value survives the ticks until it is read.
+        assert_eq!(read_count(&mut pit, 0), 0x1000);
+        assert_eq!(read_count(&mut pit, 0), 0x1000 - 4);
+    }
+
+    #[test]
+    fn test_one_shot_wraps() {
+        let mut pit = Pit::new();
+        // Counter 1, low byte only, mode 0.
+        pit.write(PIT_CONTROL_PORT, 0b0101_0000);
+        pit.write(PIT_COUNTER_PORTS[1], 2);
+
+        pit.tick(3 * CPU_CYCLES_PER_PIT_TICK);
+        assert_eq!(pit.expirations(1), 1);
+        assert_eq!(pit.read(PIT_COUNTER_PORTS[1]), 0xFF);
+    }
+}
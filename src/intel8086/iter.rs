@@ -0,0 +1,218 @@
+use super::error::*;
+use super::instructions::*;
+use log::debug;
+use std::io::{ErrorKind, Read};
+
+// Decodes instructions lazily from a byte slice. Addresses are offsets into the slice.
+// The first error is returned once and then the iteration stops.
+pub struct InstructionIter<'a> {
+    bytes: &'a [u8],
+    address: usize,
+    done: bool,
+}
+
+impl<'a> InstructionIter<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        InstructionIter {
+            bytes,
+            address: 0,
+            done: false,
+        }
+    }
+}
+
+impl Iterator for InstructionIter<'_> {
+    type Item = Result<Instruction, IntelError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.address >= self.bytes.len() {
+            return None;
+        }
+
+        let result = decode_at(&self.bytes[self.address..], self.address);
+        match &result {
+            Ok(instruction) => self.address += instruction.len(),
+            Err(_) => self.done = true,
+        }
+        Some(result)
+    }
+}
+
+// Same as |InstructionIter|, but pulls the bytes from a reader as they are needed, so the whole
+// program never has to be in memory. Addresses are offsets from where the reader started.
+pub struct InstructionReader<R: Read> {
+    reader: R,
+    buffer: Vec<u8>,
+    // Where the next instruction starts within |buffer|.
+    start: usize,
+    address: usize,
+    eof: bool,
+    done: bool,
+}
+
+impl<R: Read> InstructionReader<R> {
+    pub fn new(reader: R) -> Self {
+        InstructionReader {
+            reader,
+            buffer: Vec::with_capacity(READ_CHUNK_SIZE),
+            start: 0,
+            address: 0,
+            eof: false,
+            done: false,
+        }
+    }
+
+    // Makes sure a whole instruction is buffered, unless the reader runs out before that.
+    fn fill(&mut self) -> Result<(), IntelError> {
+        while !self.eof && self.buffer.len() - self.start < MAX_INSTRUCTION_LEN {
+            // Drop what was already decoded before growing the buffer.
+            self.buffer.drain(..self.start);
+            self.start = 0;
+
+            let mut chunk = [0u8; READ_CHUNK_SIZE];
+            match self.reader.read(&mut chunk) {
+                Ok(0) => self.eof = true,
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for InstructionReader<R> {
+    type Item = Result<Instruction, IntelError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        if let Err(e) = self.fill() {
+            self.done = true;
+            return Some(Err(e));
+        }
+
+        if self.start >= self.buffer.len() {
+            return None;
+        }
+
+        let result = decode_at(&self.buffer[self.start..], self.address);
+        match &result {
+            Ok(instruction) => {
+                self.start += instruction.len();
+                self.address += instruction.len();
+            }
+            Err(_) => self.done = true,
+        }
+        Some(result)
+    }
+}
+
+// HELPERS -----------------------------------------------------------------------------------------
+
+const MAX_INSTRUCTION_LEN: usize = 6;
+const READ_CHUNK_SIZE: usize = 4096;
+
+fn decode_at(bytes: &[u8], address: usize) -> Result<Instruction, IntelError> {
+    let mut instruction = Instruction::decode(bytes)?;
+    instruction.address = address;
+
+    debug!("\n{:?}", instruction);
+    Ok(instruction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Error};
+
+    // mov cx, 3
+    // sub cx, 1
+    // jnz back to the sub
+    const PROGRAM: &[u8] = &[0xB9, 0x03, 0x00, 0x83, 0xE9, 0x01, 0x75, 0xFB];
+
+    // Hands out one byte per read, to exercise instructions split across reads.
+    struct ByteByByte<'a>(&'a [u8]);
+
+    impl Read for ByteByByte<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let Some((first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            buf[0] = *first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    struct Failing;
+
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(Error::other("broken"))
+        }
+    }
+
+    fn addresses(iter: impl Iterator<Item = Result<Instruction, IntelError>>) -> Vec<usize> {
+        iter.map(|i| i.unwrap().address).collect()
+    }
+
+    #[test]
+    fn test_slice_addresses() {
+        assert_eq!(addresses(InstructionIter::new(PROGRAM)), vec![0, 3, 6]);
+        assert_eq!(InstructionIter::new(&[]).count(), 0);
+    }
+
+    #[test]
+    fn test_stops_after_error() {
+        // mov cx, 3 followed by an unsupported opcode.
+        let bytes = [0xB9, 0x03, 0x00, 0xF4, 0xB9, 0x03, 0x00];
+        let results: Vec<_> = InstructionIter::new(&bytes).collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(IntelError::UnsupportedOpcode(0xF4))));
+
+        let results: Vec<_> = InstructionReader::new(Cursor::new(bytes)).collect();
+        assert_eq!(results.len(), 2);
+        assert!(matches!(results[1], Err(IntelError::UnsupportedOpcode(0xF4))));
+    }
+
+    #[test]
+    fn test_reader_matches_slice() {
+        // Big enough to cross a few read chunks.
+        let bytes: Vec<u8> = PROGRAM
+            .iter()
+            .copied()
+            .cycle()
+            .take(PROGRAM.len() * 2000)
+            .collect();
+
+        let want: Vec<Instruction> = InstructionIter::new(&bytes).map(Result::unwrap).collect();
+        let got: Vec<Instruction> = InstructionReader::new(Cursor::new(&bytes))
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(got, want);
+
+        let got: Vec<Instruction> = InstructionReader::new(ByteByByte(&bytes))
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn test_reader_incomplete_tail() {
+        // mov cx, <missing high byte>
+        let results: Vec<_> = InstructionReader::new(Cursor::new([0xB9, 0x03])).collect();
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0], Err(IntelError::IncompleteByteStream)));
+    }
+
+    #[test]
+    fn test_reader_io_error() {
+        let results: Vec<_> = InstructionReader::new(Failing).collect();
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0], Err(IntelError::Io(_))));
+    }
+}
@@ -0,0 +1,111 @@
+use super::instructions::*;
+use super::syntax::*;
+use std::collections::BTreeSet;
+
+// Two pass disassembly: first we collect every jump target that lands on an instruction boundary,
+// then we print the listing with "label_XXXX:" lines at those addresses.
+// Targets that land in the middle of an instruction (or outside the program) keep the $ notation.
+pub fn to_asm_with_labels(instructions: &[Instruction], formatter: &dyn SyntaxFormatter) -> String {
+    let labels = collect_labels(instructions);
+
+    let mut string = format!("{}\n\n", formatter.header());
+    for instruction in instructions {
+        if labels.contains(&instruction.address) {
+            string.push_str(&formatter.label(&label_name(instruction.address)));
+            string.push('\n');
+        }
+
+        string.push_str(&format_instruction(instruction, &labels, formatter));
+        string.push('\n');
+    }
+
+    // A jump can land right after the last instruction.
+    let end = program_end(instructions);
+    if labels.contains(&end) {
+        string.push_str(&formatter.label(&label_name(end)));
+        string.push('\n');
+    }
+
+    string
+}
+
+// Returns the jump targets that can be referred by a label.
+pub fn collect_labels(instructions: &[Instruction]) -> BTreeSet<usize> {
+    let mut boundaries: BTreeSet<usize> = instructions.iter().map(|i| i.address).collect();
+    boundaries.insert(program_end(instructions));
+
+    instructions
+        .iter()
+        .filter_map(Instruction::jump_target)
+        .filter(|target| boundaries.contains(target))
+        .collect()
+}
+
+// Prints the instruction, referring to its jump target by name if it has a label.
+pub fn format_instruction(
+    instruction: &Instruction,
+    labels: &BTreeSet<usize>,
+    formatter: &dyn SyntaxFormatter,
+) -> String {
+    match instruction.jump_target() {
+        Some(target) if labels.contains(&target) => {
+            formatter.jump_to_label(instruction, &label_name(target))
+        }
+        _ => formatter.instruction(instruction),
+    }
+}
+
+pub fn label_name(address: usize) -> String {
+    format!("label_{:04X}", address)
+}
+
+fn program_end(instructions: &[Instruction]) -> usize {
+    match instructions.last() {
+        Some(last) => last.address + last.len(),
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intel8086::disassemble;
+
+    #[test]
+    fn test_backwards_jump() {
+        // mov cx, 3
+        // top:
+        // sub cx, 1
+        // jnz top
+        let bytes = [0xB9, 0x03, 0x00, 0x83, 0xE9, 0x01, 0x75, 0xFB];
+        let instructions = disassemble(&bytes).unwrap();
+
+        let asm = to_asm_with_labels(&instructions, &Nasm);
+        assert_eq!(asm, "bits 16\n\nmov cx, 3\nlabel_0003:\nsub cx, 1\njne label_0003\n");
+    }
+
+    #[test]
+    fn test_forward_jump_to_end() {
+        // je end
+        // mov cx, 3
+        // end:
+        let bytes = [0x74, 0x03, 0xB9, 0x03, 0x00];
+        let instructions = disassemble(&bytes).unwrap();
+
+        let asm = to_asm_with_labels(&instructions, &Nasm);
+        assert_eq!(asm, "bits 16\n\nje label_0005\nmov cx, 3\nlabel_0005:\n");
+    }
+
+    #[test]
+    fn test_mid_instruction_target_falls_back() {
+        // mov cx, 3
+        // jmp into the immediate of the mov.
+        let bytes = [0xB9, 0x03, 0x00, 0xEB, 0xFC];
+        let instructions = disassemble(&bytes).unwrap();
+
+        assert!(collect_labels(&instructions).is_empty());
+
+        let asm = to_asm_with_labels(&instructions, &Nasm);
+        assert_eq!(asm, "bits 16\n\nmov cx, 3\njmp $-2+0\n");
+    }
+}
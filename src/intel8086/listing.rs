@@ -0,0 +1,144 @@
+use super::instructions::*;
+use super::syntax::*;
+use super::tables::*;
+use super::traversal::DataBlock;
+
+// Prints the program the way ndisasm does: segment:offset, raw bytes, the instruction and the
+// static cycle estimate. Both slices must be sorted by address, which are offsets in |segment|.
+//
+// 0000:0003  8B 0E 00 10         mov cx, [4096]                ; 14 cycles (8+6ea)
+pub fn to_listing(
+    segment: u16,
+    instructions: &[Instruction],
+    data: &[DataBlock],
+    formatter: &dyn SyntaxFormatter,
+) -> String {
+    let mut lines: Vec<(usize, String)> = vec![];
+
+    for instruction in instructions {
+        let asm = formatter.instruction(instruction);
+        let line = match estimate_cycles(instruction) {
+            Some(estimate) => format_line(
+                segment,
+                instruction.address,
+                &instruction.data[..instruction.len()],
+                &format!("{:<width$}{} {}", asm, formatter.comment(), estimate, width = ASM_WIDTH),
+            ),
+            None => format_line(
+                segment,
+                instruction.address,
+                &instruction.data[..instruction.len()],
+                &asm,
+            ),
+        };
+        lines.push((instruction.address, line));
+    }
+
+    for block in data {
+        for (i, chunk) in block.bytes.chunks(MAX_BYTES_PER_LINE).enumerate() {
+            let address = block.address + i * MAX_BYTES_PER_LINE;
+            let line = format_line(segment, address, chunk, &formatter.data(chunk));
+            lines.push((address, line));
+        }
+    }
+
+    lines.sort_by_key(|(address, _)| *address);
+
+    let mut string = String::new();
+    for (_, line) in lines {
+        string.push_str(line.trim_end());
+        string.push('\n');
+    }
+    string
+}
+
+// HELPERS -----------------------------------------------------------------------------------------
+
+// Instructions are at most 6 bytes.
+pub(super) const MAX_BYTES_PER_LINE: usize = 6;
+const BYTES_WIDTH: usize = MAX_BYTES_PER_LINE * 3;
+const ASM_WIDTH: usize = 30;
+
+pub(super) fn format_line(segment: u16, offset: usize, bytes: &[u8], text: &str) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    format!(
+        "{:04X}:{:04X}  {:<width$}  {}",
+        segment,
+        offset & 0xFFFF,
+        bytes.join(" "),
+        text,
+        width = BYTES_WIDTH,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intel8086::{disassemble, disassemble_recovering};
+
+    #[test]
+    fn test_listing_line() {
+        // mov cx, 3
+        // mov cx, [4096]
+        let bytes = [0xB9, 0x03, 0x00, 0x8B, 0x0E, 0x00, 0x10];
+        let instructions = disassemble(&bytes).unwrap();
+
+        let listing = to_listing(0, &instructions, &[], &Nasm);
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "0000:0000  B9 03 00            mov cx, 3                     ; 4 cycles",
+                "0000:0003  8B 0E 00 10         mov cx, [4096]                ; 14 cycles (8+6ea)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_listing_without_timings() {
+        // jne $+0
+        let bytes = [0x75, 0xFE];
+        let instructions = disassemble(&bytes).unwrap();
+
+        assert_eq!(
+            to_listing(0, &instructions, &[], &Nasm),
+            "0000:0000  75 FE               jne $+0\n"
+        );
+    }
+
+    #[test]
+    fn test_listing_with_data() {
+        // mov cx, 3 followed by an unsupported opcode.
+        let bytes = [0xB9, 0x03, 0x00, 0xF4];
+        let result = disassemble_recovering(&bytes);
+
+        let listing = to_listing(0, &result.instructions, &result.data, &Nasm);
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines[1], "0000:0003  F4                  db 0xF4");
+    }
+
+    #[test]
+    fn test_listing_segment() {
+        // mov cx, 3
+        let bytes = [0xB9, 0x03, 0x00];
+        let instructions = disassemble(&bytes).unwrap();
+
+        let listing = to_listing(0x1234, &instructions, &[], &Att);
+        assert_eq!(
+            listing,
+            "1234:0000  B9 03 00            mov $0x3,%cx                  # 4 cycles\n"
+        );
+    }
+
+    #[test]
+    fn test_listing_segment_offsets() {
+        // mov cx, 3, an unsupported opcode and mov dx, 4.
+        let bytes = [0xB9, 0x03, 0x00, 0xF4, 0xBA, 0x04, 0x00];
+        let result = disassemble_recovering(&bytes);
+
+        // The offsets stay relative to the segment, for data as well.
+        let listing = to_listing(0x1000, &result.instructions, &result.data, &Nasm);
+        let prefixes: Vec<&str> = listing.lines().map(|line| &line[..9]).collect();
+        assert_eq!(prefixes, vec!["1000:0000", "1000:0003", "1000:0004"]);
+    }
+}
@@ -0,0 +1,181 @@
+use super::cpu::CPU;
+
+const BYTES_PER_LINE: usize = 16;
+
+// A run of consecutive bytes that differ between two memories.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MemoryChange {
+    pub address: usize,
+    pub before: Vec<u8>,
+    pub after: Vec<u8>,
+}
+
+impl std::fmt::Display for MemoryChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "0x{:05X} ({} bytes): {} -> {}",
+            self.address,
+            self.before.len(),
+            hex_bytes(&self.before),
+            hex_bytes(&self.after)
+        )
+    }
+}
+
+// "01 00 ff"
+pub fn hex_bytes(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    bytes.join(" ")
+}
+
+// Renders |len| bytes from |start| like hexdump -C: 16 bytes per line, aligned to 16, with the
+// printable ASCII on the right. Bytes of the line outside the range are left blank.
+//
+// 0x01000  01 00 02 00 0a 00 04 00  00 00 00 00 00 00 00 00  |................|
+pub fn hexdump(memory: &[u8], start: usize, len: usize) -> String {
+    let end = (start + len).min(memory.len());
+    let mut result = String::new();
+
+    let mut line_start = start - start % BYTES_PER_LINE;
+    while line_start < end {
+        let mut hex = String::new();
+        let mut ascii = String::new();
+
+        for offset in 0..BYTES_PER_LINE {
+            if offset == BYTES_PER_LINE / 2 {
+                hex.push(' ');
+            }
+
+            let address = line_start + offset;
+            let Some(byte) = memory
+                .get(address)
+                .filter(|_| address >= start && address < end)
+            else {
+                hex.push_str("   ");
+                ascii.push(' ');
+                continue;
+            };
+
+            hex.push_str(&format!(" {:02x}", byte));
+            ascii.push(if byte.is_ascii_graphic() || *byte == b' ' {
+                *byte as char
+            } else {
+                '.'
+            });
+        }
+
+        result.push_str(&format!("0x{:05X} {}  |{}|\n", line_start, hex, ascii));
+        line_start += BYTES_PER_LINE;
+    }
+
+    result
+}
+
+// Returns the ranges that differ between |before| and |after|, both starting at |address|. Only
+// the length they have in common is compared.
+pub fn diff(address: usize, before: &[u8], after: &[u8]) -> Vec<MemoryChange> {
+    let mut changes: Vec<MemoryChange> = vec![];
+
+    for (offset, (b, a)) in before.iter().zip(after).enumerate() {
+        if b == a {
+            continue;
+        }
+
+        let current = address + offset;
+        match changes.last_mut() {
+            Some(change) if change.address + change.before.len() == current => {
+                change.before.push(*b);
+                change.after.push(*a);
+            }
+            _ => changes.push(MemoryChange {
+                address: current,
+                before: vec![*b],
+                after: vec![*a],
+            }),
+        }
+    }
+
+    changes
+}
+
+// FNV-1a, to compare whole memories against a known state without storing them.
+pub fn hash(memory: &[u8]) -> u64 {
+    memory
+        .iter()
+        .fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+pub fn diff_cpus(before: &CPU, after: &CPU) -> Vec<MemoryChange> {
+    diff(0, before.get_memory(), after.get_memory())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hexdump() {
+        let mut memory = vec![0; 64];
+        memory[0x12..0x17].copy_from_slice(b"Hi 8\x01");
+
+        assert_eq!(
+            hexdump(&memory, 0x12, 5),
+            "0x00010        48 69 20 38 01                              |  Hi 8.         |\n"
+        );
+
+        let dump = hexdump(&memory, 0, 64);
+        assert_eq!(dump.lines().count(), 4);
+        assert!(dump.starts_with(
+            "0x00000  00 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|\n"
+        ));
+
+        // Past the end of memory.
+        assert_eq!(hexdump(&memory, 60, 10).lines().count(), 1);
+        assert_eq!(hexdump(&memory, 64, 10), "");
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = [0, 1, 2, 3, 4, 5, 6, 7];
+        let after = [0, 9, 9, 3, 4, 5, 6, 8];
+
+        let changes = diff(0x1000, &before, &after);
+        assert_eq!(
+            changes,
+            vec![
+                MemoryChange {
+                    address: 0x1001,
+                    before: vec![1, 2],
+                    after: vec![9, 9],
+                },
+                MemoryChange {
+                    address: 0x1007,
+                    before: vec![7],
+                    after: vec![8],
+                },
+            ]
+        );
+        assert_eq!(changes[0].to_string(), "0x01001 (2 bytes): 01 02 -> 09 09");
+
+        assert!(diff(0, &before, &before).is_empty());
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash(&[]), 0xcbf29ce484222325);
+        assert_eq!(hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(hash(&[0, 1]), hash(&[1, 0]));
+    }
+
+    #[test]
+    fn test_diff_cpus() {
+        let before = CPU::new();
+        let mut after = CPU::new();
+        after.set_program(&[0xB9, 0x03, 0x00]).unwrap();
+
+        let changes = diff_cpus(&before, &after);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].after, vec![0xB9, 0x03]);
+    }
+}
@@ -1,42 +1,186 @@
 pub mod args;
+pub mod batch;
+pub mod cache;
+pub mod constants;
 pub mod cpu;
+pub mod coverage;
+pub mod debugger;
 mod decoding;
+mod encoding;
 pub mod error;
+pub mod estimate;
+pub mod expectations;
+pub mod fuzzing;
 pub mod instructions;
+pub mod io;
+pub mod iter;
+pub mod labels;
+pub mod listing;
+pub mod memory;
 pub mod registers;
+pub mod syntax;
 pub mod tables;
+pub mod traversal;
+pub mod video;
 
 use cpu::*;
 use error::IntelError;
 use instructions::*;
+use io::IoBus;
+use iter::*;
 use log::*;
+use registers::*;
+use traversal::*;
 
-pub fn disassemble(mut bytes: &[u8]) -> Result<Vec<Instruction>, IntelError> {
+pub fn disassemble(bytes: &[u8]) -> Result<Vec<Instruction>, IntelError> {
+    InstructionIter::new(bytes).collect()
+}
+
+// An instruction that could not be decoded, kept with the address where decoding was attempted.
+#[derive(Debug, serde::Serialize)]
+pub struct Diagnostic {
+    pub address: usize,
+    #[serde(serialize_with = "serialize_display")]
+    pub error: IntelError,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "0x{:04X}: {}", self.address, self.error)
+    }
+}
+
+fn serialize_display<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: std::fmt::Display,
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&value.to_string())
+}
+
+#[derive(serde::Serialize)]
+pub struct DisassemblyResult {
+    pub instructions: Vec<Instruction>,
+    // Bytes that could not be decoded, sorted by address.
+    pub data: Vec<DataBlock>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl DisassemblyResult {
+    pub fn to_asm(&self) -> String {
+        to_asm_with_data(&self.instructions, &self.data, &syntax::Nasm)
+    }
+}
+
+// Like |disassemble|, but a byte that cannot be decoded is kept as data and decoding resumes at
+// the next byte. Every failed attempt is reported as a diagnostic.
+pub fn disassemble_recovering(bytes: &[u8]) -> DisassemblyResult {
     let mut instructions = vec![];
+    let mut data: Vec<DataBlock> = vec![];
+    let mut diagnostics = vec![];
 
-    while !bytes.is_empty() {
-        let instruction = Instruction::decode(bytes)?;
-        bytes = &bytes[instruction.len()..];
+    let mut address = 0;
+    while address < bytes.len() {
+        match Instruction::decode(&bytes[address..]) {
+            Ok(mut instruction) => {
+                instruction.address = address;
+                address += instruction.len();
 
-        debug!("\n{:?}", instruction);
-        instructions.push(instruction);
+                debug!("\n{:?}", instruction);
+                instructions.push(instruction);
+            }
+            Err(error) => {
+                debug!("Cannot decode at {}: {}", printu16(address as u16), error);
+
+                match data.last_mut() {
+                    Some(block) if block.address + block.bytes.len() == address => {
+                        block.bytes.push(bytes[address])
+                    }
+                    _ => data.push(DataBlock {
+                        address,
+                        bytes: vec![bytes[address]],
+                    }),
+                }
+
+                diagnostics.push(Diagnostic { address, error });
+                address += 1;
+            }
+        }
     }
 
-    Ok(instructions)
+    DisassemblyResult {
+        instructions,
+        data,
+        diagnostics,
+    }
 }
 
+#[derive(serde::Serialize)]
 pub struct SimulationResult {
     pub cpu: CPU,
     pub executed_instructions: Vec<Instruction>,
     pub cycles: usize,
 }
 
+// Where the program goes and what happens around it while it runs.
+#[derive(Default)]
+pub struct SimulationOptions {
+    // The program is loaded at segment:0, and all the segment registers point there. Segment 0 is
+    // where the interrupt vector table is, so programs that take interrupts need another one.
+    pub segment: u16,
+    // Answers the in/out instructions.
+    pub io: IoBus,
+    // Handlers in the program as (vector, offset).
+    pub vectors: Vec<(u8, u16)>,
+    // External interrupts as (instruction count, vector), see |CPU::raise_interrupt_at|.
+    pub interrupts: Vec<(usize, u8)>,
+    // Called after every instruction, like |debugger::Debugger::after_instruction|.
+    pub on_instruction: Option<InstructionHook>,
+    // Gives up with IntelError::InstructionLimit once this many instructions ran without the IP
+    // leaving the program.
+    pub max_instructions: Option<usize>,
+}
+
+pub type InstructionHook = Box<dyn FnMut(&CPU, &Instruction) -> Result<(), IntelError>>;
+
 pub fn simulate(program: &[u8]) -> Result<SimulationResult, IntelError> {
+    simulate_with(program, SimulationOptions::default())
+}
+
+// Same as |simulate|, with |io| answering the in/out instructions.
+pub fn simulate_with_io(program: &[u8], io: IoBus) -> Result<SimulationResult, IntelError> {
+    simulate_with(
+        program,
+        SimulationOptions {
+            io,
+            ..Default::default()
+        },
+    )
+}
+
+pub fn simulate_with(
+    program: &[u8],
+    options: SimulationOptions,
+) -> Result<SimulationResult, IntelError> {
     let mut cpu = CPU::new();
+    cpu.io = options.io;
 
     // We copy the input bytes into the cpu memory.
-    cpu.set_program(program)?;
+    let segment = options.segment;
+    cpu.set_program_at(segment, program)?;
+    for reg in [REGISTER_DS, REGISTER_ES, REGISTER_SS] {
+        cpu.set_register(&reg, segment);
+    }
 
+    for (vector, offset) in options.vectors {
+        cpu.set_interrupt_vector(vector, segment, offset);
+    }
+    for (instruction_count, vector) in options.interrupts {
+        cpu.raise_interrupt_at(instruction_count, vector);
+    }
+
+    let start = physical_address(segment, 0);
+    let mut on_instruction = options.on_instruction;
     let mut executed_instructions = vec![];
 
     let mut cycles: usize = 0;
@@ -44,30 +188,40 @@ pub fn simulate(program: &[u8]) -> Result<SimulationResult, IntelError> {
         let address = cpu.ip_address();
 
         // For now we simulate until the IP is out of the original program bounds.
-        if address >= program.len() {
+        if !(start..start + program.len()).contains(&address) {
             break;
         }
 
-        debug!("Decoding at {}", printu16(address as u16));
-
-        // Get the current bytestream for the instruction to decode.
-        let bytestream = &cpu.get_memory()[address..];
-        if bytestream.is_empty() {
-            panic!();
+        if let Some(max_instructions) = options.max_instructions {
+            if executed_instructions.len() >= max_instructions {
+                return Err(IntelError::InstructionLimit(max_instructions));
+            }
         }
 
-        // Decode the instruction.
-        let instruction = Instruction::decode(bytestream)?;
+        debug!("Decoding at {}", printu16(address as u16));
+
+        // Decode the instruction (or take it from the cache).
+        let instruction = cpu.fetch(address)?;
         debug!("\n{:?}", instruction);
 
         // Simulate the instruction into the cpu.
         cycles += cpu.simulate(&instruction)?;
 
+        if let Some(on_instruction) = &mut on_instruction {
+            on_instruction(&cpu, &instruction)?;
+        }
+
         executed_instructions.push(instruction);
     }
 
     info!("Total cycles: {}", cycles);
 
+    let stats = cpu.cache_stats();
+    info!(
+        "Instruction cache: {} hits, {} misses, {} invalidations",
+        stats.hits, stats.misses, stats.invalidations
+    );
+
     let result = SimulationResult {
         cpu,
         executed_instructions,
@@ -83,3 +237,174 @@ pub fn to_asm(instructions: &Vec<Instruction>) -> String {
     let string: String = instruction_strings.into_iter().collect();
     format!("bits 16\n\n{}", string)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_buffer() {
+        #[rustfmt::skip]
+        let program = [
+            0xB8, 0x00, 0xB8,                   // mov ax, 0xB800
+            0x8E, 0xD8,                         // mov ds, ax
+            0xC7, 0x06, 0x00, 0x00, 0x48, 0x07, // mov word [0], 0x0748
+            0xC7, 0x06, 0x02, 0x00, 0x69, 0x07, // mov word [2], 0x0769
+        ];
+
+        let result = simulate(&program).unwrap();
+        assert_eq!(result.cpu.ds(), 0xB800);
+
+        let screen = video::render_text_buffer(result.cpu.get_memory(), false);
+        assert_eq!(screen.lines().next(), Some("Hi"));
+    }
+
+    #[test]
+    fn test_disassemble_recovering() {
+        // mov cx, 3
+        // two unsupported opcodes.
+        // sub cx, 1
+        let bytes = [0xB9, 0x03, 0x00, 0xF4, 0x90, 0x83, 0xE9, 0x01];
+        assert!(disassemble(&bytes).is_err());
+
+        let result = disassemble_recovering(&bytes);
+        assert_eq!(result.instructions.len(), 2);
+        assert_eq!(result.instructions[1].address, 5);
+
+        let addresses: Vec<usize> = result.diagnostics.iter().map(|d| d.address).collect();
+        assert_eq!(addresses, vec![3, 4]);
+        assert!(matches!(result.diagnostics[0].error, IntelError::UnsupportedOpcode(0xF4)));
+
+        assert_eq!(result.to_asm(), "bits 16\n\nmov cx, 3\ndb 0xF4, 0x90\nsub cx, 1\n");
+    }
+
+    #[test]
+    fn test_disassemble_recovering_incomplete_tail() {
+        // mov cx, <missing byte>
+        let bytes = [0xB9, 0x03];
+
+        let result = disassemble_recovering(&bytes);
+        assert!(result.instructions.is_empty());
+        assert!(matches!(result.diagnostics[0].error, IntelError::IncompleteByteStream));
+        assert_eq!(result.to_asm(), "bits 16\n\ndb 0xB9, 0x03\n");
+    }
+
+    #[test]
+    fn test_serialize_instruction() {
+        // mov cx, [bx + si + 4]
+        let bytes = [0x8B, 0x48, 0x04];
+        let instructions = disassemble(&bytes).unwrap();
+
+        let json = serde_json::to_value(&instructions[0]).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "address": 0,
+                "bytes": [0x8B, 0x48, 0x04],
+                "operation": "mov",
+                "wide": true,
+                "dst": { "type": "register", "value": "cx" },
+                "src": { "type": "eac", "value": { "base": "bx_si", "displacement": 4 } },
+            })
+        );
+    }
+
+    #[test]
+    fn test_serialize_simulation() {
+        // mov cx, 3
+        // sub cx, 3
+        let bytes = [0xB9, 0x03, 0x00, 0x83, 0xE9, 0x03];
+        let result = simulate(&bytes).unwrap();
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["cpu"]["cx"], 0);
+        assert_eq!(json["cpu"]["ip"], 6);
+        assert_eq!(json["cpu"]["flags"]["z"], true);
+        assert_eq!(json["cycles"], 8);
+        assert_eq!(json["executed_instructions"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_port_io() {
+        // in al, 0x60
+        // mov bx, ax
+        // mov dx, 0x21
+        // out dx, al ; Mask the PIC with the scancode.
+        // in al, 0x64
+        // mov cx, ax
+        // in al, dx
+        let bytes = [
+            0xE4, 0x60, 0x89, 0xC3, 0xBA, 0x21, 0x00, 0xEE, 0xE4, 0x64, 0x89, 0xC1, 0xEC,
+        ];
+        let result = simulate_with_io(&bytes, IoBus::pc([0x1E])).unwrap();
+
+        assert_eq!(result.cpu.bx(), 0x1E);
+        // The keyboard queue is empty now, only the system flag is left.
+        assert_eq!(result.cpu.cx(), 0x04);
+        assert_eq!(result.cpu.ax(), 0x1E);
+    }
+
+    #[test]
+    fn test_scheduled_interrupt() {
+        #[rustfmt::skip]
+        let program = [
+            0xB9, 0x03, 0x00, // mov cx, 3
+            0xFB,             // sti
+            0x83, 0xE9, 0x01, // sub cx, 1
+            0x75, 0xFB,       // jne back to the sub
+            0x74, 0x04,       // je past the handler
+            0xBA, 0x07, 0x00, // handler: mov dx, 7
+            0xCF,             // iret
+        ];
+        let options = SimulationOptions {
+            segment: 0x100,
+            vectors: vec![(0x40, 11)],
+            interrupts: vec![(1, 0x40)],
+            ..Default::default()
+        };
+        let result = simulate_with(&program, options).unwrap();
+
+        // The program is above the vector table, so it is still there when the interrupt comes.
+        assert_eq!((result.cpu.cs(), result.cpu.ds(), result.cpu.ip()), (0x100, 0x100, 15));
+        assert_eq!((result.cpu.cx(), result.cpu.dx()), (0, 7));
+        assert_eq!(result.executed_instructions.len(), 11);
+        assert_eq!(result.executed_instructions[3].to_string(), "mov dx, 7");
+    }
+
+    #[test]
+    fn test_instruction_limit() {
+        // jmp $
+        let options = SimulationOptions {
+            max_instructions: Some(100),
+            ..Default::default()
+        };
+        let result = simulate_with(&[0xEB, 0xFE], options);
+        assert!(matches!(result, Err(IntelError::InstructionLimit(100))));
+    }
+
+    #[test]
+    fn test_self_modifying_code() {
+        // mov bx, 2
+        // top:
+        // mov cx, 1
+        // mov word [4], 7 ; Patches the immediate of the mov above.
+        // sub bx, 1
+        // jnz top
+        let bytes = [
+            0xBB, 0x02, 0x00, 0xB9, 0x01, 0x00, 0xC7, 0x06, 0x04, 0x00, 0x07, 0x00, 0x83, 0xEB,
+            0x01, 0x75, 0xF2,
+        ];
+        let result = simulate(&bytes).unwrap();
+
+        // The second time around the mov has to be decoded again.
+        assert_eq!(result.cpu.cx(), 7);
+        assert_eq!(result.executed_instructions[1].to_string(), "mov cx, 1");
+        assert_eq!(result.executed_instructions[5].to_string(), "mov cx, 7");
+
+        // Everything but the patched mov comes from the cache the second time.
+        let stats = result.cpu.cache_stats();
+        assert_eq!(stats.misses, 6);
+        assert_eq!(stats.hits, 3);
+        assert!(stats.invalidations >= 1);
+    }
+}
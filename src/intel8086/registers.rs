@@ -29,6 +29,29 @@ impl Register {
         }
     }
 
+    // Returns the 3-bit value used in the reg/rm fields for this register.
+    // Byte registers are not ordered by |reg|, so we look them up in the encoding tables.
+    pub fn encoding(&self) -> Option<u8> {
+        let table: &[Register] = match self.size {
+            1 => &REGISTERS_BYTE,
+            2 => &REGISTERS_WORD,
+            _ => return None,
+        };
+
+        table
+            .iter()
+            .position(|reg| reg == self)
+            .map(|index| index as u8)
+    }
+
+    // Returns the 2-bit value used in the sr field, if this is a segment register.
+    pub fn segment_encoding(&self) -> Option<u8> {
+        SEGMENT_REGISTERS
+            .iter()
+            .position(|reg| reg == self)
+            .map(|index| index as u8)
+    }
+
     pub fn find(name: &str) -> Option<Register> {
         for reg in REGISTERS_WORD {
             if reg.name == name {
@@ -48,7 +71,7 @@ impl Register {
             }
         }
 
-        None
+        SEGMENT_REGISTERS.into_iter().find(|reg| reg.name == name)
     }
 }
 
@@ -97,8 +120,18 @@ pub const REGISTER_IP: Register = Register::new("ip", 2, 8);
 
 pub(super) const EXTRA_REGISTERS: [Register; 1] = [REGISTER_IP];
 
+pub const REGISTER_ES: Register = Register::new("es", 2, 9);
+pub const REGISTER_CS: Register = Register::new("cs", 2, 10);
+pub const REGISTER_SS: Register = Register::new("ss", 2, 11);
+pub const REGISTER_DS: Register = Register::new("ds", 2, 12);
+
+// In the order of the sr field.
+pub(super) const SEGMENT_REGISTERS: [Register; 4] =
+    [REGISTER_ES, REGISTER_CS, REGISTER_SS, REGISTER_DS];
+
 // Represents the Effective Address Calculation plus any optional offset.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "base", content = "displacement", rename_all = "snake_case")]
 pub enum EAC {
     BxSi(u16),
     BxDi(u16),
@@ -246,3 +279,9 @@ impl std::fmt::Display for Register {
         write!(f, "{}", self.name)
     }
 }
+
+impl serde::Serialize for Register {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name)
+    }
+}
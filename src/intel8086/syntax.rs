@@ -0,0 +1,330 @@
+use super::args::AsmSyntax;
+use super::encoding::is_wide;
+use super::instructions::*;
+use super::registers::*;
+
+// Knows how to print instructions (and the bits around them) in a given assembler syntax.
+pub trait SyntaxFormatter {
+    // First line of a listing, before any instruction.
+    fn header(&self) -> &'static str;
+
+    fn instruction(&self, instruction: &Instruction) -> String;
+
+    // Prints a jump referring to its target by |label| instead of an offset.
+    fn jump_to_label(&self, instruction: &Instruction, label: &str) -> String {
+        format!("{} {}", instruction.operation, label)
+    }
+
+    fn label(&self, name: &str) -> String {
+        format!("{}:", name)
+    }
+
+    fn data(&self, bytes: &[u8]) -> String;
+
+    // Starts a comment that runs to the end of the line.
+    fn comment(&self) -> &'static str {
+        ";"
+    }
+}
+
+pub fn formatter(syntax: &AsmSyntax) -> Box<dyn SyntaxFormatter> {
+    match syntax {
+        AsmSyntax::Nasm => Box::new(Nasm),
+        AsmSyntax::Masm => Box::new(Masm),
+        AsmSyntax::Att => Box::new(Att),
+    }
+}
+
+pub fn to_asm_with_syntax(instructions: &[Instruction], formatter: &dyn SyntaxFormatter) -> String {
+    let mut string = format!("{}\n\n", formatter.header());
+    for instruction in instructions {
+        string.push_str(&formatter.instruction(instruction));
+        string.push('\n');
+    }
+    string
+}
+
+// NASM --------------------------------------------------------------------------------------------
+
+// The Display implementations of the instructions already print NASM.
+pub struct Nasm;
+
+impl SyntaxFormatter for Nasm {
+    fn header(&self) -> &'static str {
+        "bits 16"
+    }
+
+    fn instruction(&self, instruction: &Instruction) -> String {
+        instruction.to_string()
+    }
+
+    fn data(&self, bytes: &[u8]) -> String {
+        let values: Vec<String> = bytes.iter().map(|b| format!("0x{:02X}", b)).collect();
+        format!("db {}", values.join(", "))
+    }
+}
+
+// MASM --------------------------------------------------------------------------------------------
+
+// mov word ptr [bp+4], 5
+pub struct Masm;
+
+impl SyntaxFormatter for Masm {
+    fn header(&self) -> &'static str {
+        ".8086"
+    }
+
+    fn instruction(&self, instruction: &Instruction) -> String {
+        let operation = &instruction.operation;
+
+        if let Operand::JumpOffset(offset) = instruction.src {
+            // $ is the start of the current instruction, so we add the 2 bytes of the jump.
+            return format!("{} ${:+}", operation, offset as i16 + 2);
+        }
+
+        if !instruction.dst.is_valid() && !instruction.src.is_valid() {
+            return operation.to_string();
+        }
+
+        if !instruction.src.is_valid() {
+            let mut dst = self.operand(&instruction.dst);
+            if let Operand::EAC(_) = instruction.dst {
+                let wide = is_wide(instruction).unwrap_or(false);
+                dst = format!("{} ptr {}", if wide { "word" } else { "byte" }, dst);
+            }
+            return format!("{} {}", operation, dst);
+        }
+
+        let mut dst = self.operand(&instruction.dst);
+        if !instruction.src.has_size() && !instruction.dst.has_size() {
+            let wide = is_wide(instruction).unwrap_or(false);
+            dst = format!("{} ptr {}", if wide { "word" } else { "byte" }, dst);
+        }
+
+        format!("{} {}, {}", operation, dst, self.operand(&instruction.src))
+    }
+
+    fn data(&self, bytes: &[u8]) -> String {
+        let values: Vec<String> = bytes.iter().map(|b| format!("0{:02X}h", b)).collect();
+        format!("db {}", values.join(", "))
+    }
+}
+
+impl Masm {
+    fn operand(&self, operand: &Operand) -> String {
+        match operand {
+            Operand::EAC(eac) => {
+                let (base, offset) = eac_parts(eac);
+                match (base, offset) {
+                    (None, address) => format!("ds:[{}]", address),
+                    (Some(base), 0) => format!("[{}]", base.join("+")),
+                    // Displacements are sign-extended, so we show them as signed values.
+                    (Some(base), offset) => format!("[{}{:+}]", base.join("+"), offset as i16),
+                }
+            }
+            _ => operand.to_string(),
+        }
+    }
+}
+
+// AT&T --------------------------------------------------------------------------------------------
+
+// Follows what GNU objdump -M i8086 prints: source first, % registers, $ immediates and absolute
+// jump targets.
+// movw $0x5,0x4(%bp)
+pub struct Att;
+
+impl SyntaxFormatter for Att {
+    fn header(&self) -> &'static str {
+        ".code16"
+    }
+
+    fn instruction(&self, instruction: &Instruction) -> String {
+        let operation = &instruction.operation;
+
+        if let Operand::JumpOffset(offset) = instruction.src {
+            return match instruction.jump_target() {
+                Some(target) => format!("{} 0x{:x}", operation, target),
+                None => format!("{} .{:+}", operation, offset as i16 + 2),
+            };
+        }
+
+        if !instruction.dst.is_valid() && !instruction.src.is_valid() {
+            return operation.to_string();
+        }
+
+        if !instruction.src.is_valid() {
+            let mut mnemonic = operation.to_string();
+            if let Operand::EAC(_) = instruction.dst {
+                let wide = is_wide(instruction).unwrap_or(false);
+                mnemonic.push(if wide { 'w' } else { 'b' });
+            }
+            return format!("{} {}", mnemonic, self.operand(&instruction.dst));
+        }
+
+        // The size suffix is only needed when no register tells the operand size.
+        let mut mnemonic = operation.to_string();
+        if !instruction.src.has_size() && !instruction.dst.has_size() {
+            let wide = is_wide(instruction).unwrap_or(false);
+            mnemonic.push(if wide { 'w' } else { 'b' });
+        }
+
+        format!(
+            "{} {},{}",
+            mnemonic,
+            self.operand(&instruction.src),
+            self.operand(&instruction.dst)
+        )
+    }
+
+    fn data(&self, bytes: &[u8]) -> String {
+        let values: Vec<String> = bytes.iter().map(|b| format!("0x{:02x}", b)).collect();
+        format!(".byte {}", values.join(", "))
+    }
+
+    fn comment(&self) -> &'static str {
+        "#"
+    }
+}
+
+impl Att {
+    fn operand(&self, operand: &Operand) -> String {
+        match operand {
+            Operand::Invalid => "<invalid>".to_string(),
+            Operand::Register(register) => format!("%{}", register),
+            Operand::Immediate(value) => format!("$0x{:x}", value),
+            Operand::EAC(eac) => {
+                let (base, offset) = eac_parts(eac);
+                let Some(base) = base else {
+                    return format!("0x{:x}", offset);
+                };
+
+                let registers: Vec<String> = base.iter().map(|r| format!("%{}", r)).collect();
+                let registers = registers.join(",");
+
+                // Displacements are sign-extended, so we show them as signed values.
+                let offset = offset as i16;
+                if offset == 0 {
+                    format!("({})", registers)
+                } else if offset < 0 {
+                    format!("-0x{:x}({})", offset.unsigned_abs(), registers)
+                } else {
+                    format!("0x{:x}({})", offset, registers)
+                }
+            }
+            Operand::JumpOffset(offset) => format!(".{:+}", *offset as i16 + 2),
+        }
+    }
+}
+
+// HELPERS -----------------------------------------------------------------------------------------
+
+// Returns the registers used by the address calculation (None for direct access) and the offset.
+fn eac_parts(eac: &EAC) -> (Option<Vec<&'static str>>, u16) {
+    match eac {
+        EAC::BxSi(offset) => (Some(vec![REGISTER_BX.name, REGISTER_SI.name]), *offset),
+        EAC::BxDi(offset) => (Some(vec![REGISTER_BX.name, REGISTER_DI.name]), *offset),
+        EAC::BpSi(offset) => (Some(vec![REGISTER_BP.name, REGISTER_SI.name]), *offset),
+        EAC::BpDi(offset) => (Some(vec![REGISTER_BP.name, REGISTER_DI.name]), *offset),
+        EAC::Si(offset) => (Some(vec![REGISTER_SI.name]), *offset),
+        EAC::Di(offset) => (Some(vec![REGISTER_DI.name]), *offset),
+        EAC::Bp(offset) => (Some(vec![REGISTER_BP.name]), *offset),
+        EAC::Bx(offset) => (Some(vec![REGISTER_BX.name]), *offset),
+        EAC::DirectAccess(address) => (None, *address),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intel8086::disassemble;
+
+    // mov cx, bx
+    // add word [bp + si + 1000], 29
+    // mov ax, [2321]
+    // mov al, [bx + si + 4]
+    // sub word [bp - 2], 3
+    // jne back to the start
+    const PROGRAM: &[u8] = &[
+        0x89, 0xD9, 0x83, 0x82, 0xE8, 0x03, 0x1D, 0xA1, 0x11, 0x09, 0x8A, 0x40, 0x04, 0x83, 0x6E,
+        0xFE, 0x03, 0x75, 0xED,
+    ];
+
+    fn lines(formatter: &dyn SyntaxFormatter) -> Vec<String> {
+        let instructions = disassemble(PROGRAM).unwrap();
+        instructions
+            .iter()
+            .map(|i| formatter.instruction(i))
+            .collect()
+    }
+
+    #[test]
+    fn test_nasm() {
+        assert_eq!(
+            lines(&Nasm),
+            vec![
+                "mov cx, bx",
+                "add word [bp + si + 1000], 29",
+                "mov ax, [2321]",
+                "mov al, [bx + si + 4]",
+                "sub word [bp + 65534], 3",
+                "jne $-17+0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_masm() {
+        assert_eq!(
+            lines(&Masm),
+            vec![
+                "mov cx, bx",
+                "add word ptr [bp+si+1000], 29",
+                "mov ax, ds:[2321]",
+                "mov al, [bx+si+4]",
+                "sub word ptr [bp-2], 3",
+                "jne $-17",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_masm_negative_displacement() {
+        // mov dx, [bx + di - 300]
+        // mov byte [si - 128], 7
+        let bytes = [0x8B, 0x91, 0xD4, 0xFE, 0xC6, 0x44, 0x80, 0x07];
+        let instructions = disassemble(&bytes).unwrap();
+
+        assert_eq!(Masm.instruction(&instructions[0]), "mov dx, [bx+di-300]");
+        assert_eq!(Masm.instruction(&instructions[1]), "mov byte ptr [si-128], 7");
+    }
+
+    #[test]
+    fn test_att() {
+        assert_eq!(
+            lines(&Att),
+            vec![
+                "mov %bx,%cx",
+                "addw $0x1d,0x3e8(%bp,%si)",
+                "mov 0x911,%ax",
+                "mov 0x4(%bx,%si),%al",
+                "subw $0x3,-0x2(%bp)",
+                "jne 0x0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_data() {
+        assert_eq!(Nasm.data(&[0xF4, 0x90]), "db 0xF4, 0x90");
+        assert_eq!(Masm.data(&[0xF4, 0x90]), "db 0F4h, 090h");
+        assert_eq!(Att.data(&[0xF4, 0x90]), ".byte 0xf4, 0x90");
+    }
+
+    #[test]
+    fn test_comment() {
+        assert_eq!(Nasm.comment(), ";");
+        assert_eq!(Masm.comment(), ";");
+        assert_eq!(Att.comment(), "#");
+    }
+}
@@ -18,6 +18,8 @@ pub(super) enum OperandKind {
     Register,
     Accumulator,
     Immediate,
+    // For instructions with fewer than two operands.
+    None,
 }
 
 #[derive(Debug)]
@@ -72,6 +74,7 @@ fn operand_matches(operand: &Operand, operand_kind: &OperandKind) -> bool {
             false
         }
         OperandKind::Immediate => matches!(operand, Operand::Immediate(_)),
+        OperandKind::None => !operand.is_valid(),
     }
 }
 
@@ -79,6 +82,7 @@ const MEMORY: OperandKind = OperandKind::Memory;
 const REGISTER: OperandKind = OperandKind::Register;
 const ACCUMULATOR: OperandKind = OperandKind::Accumulator;
 const IMMEDIATE: OperandKind = OperandKind::Immediate;
+const NONE: OperandKind = OperandKind::None;
 
 pub(super) static COST_MAP: Lazy<Mutex<HashMap<Operation, Vec<InstructionCost>>>> =
     Lazy::new(|| {
@@ -136,6 +140,52 @@ pub(super) static COST_MAP: Lazy<Mutex<HashMap<Operation, Vec<InstructionCost>>>
             ],
         );
 
+        // IN. The port is either an immediate or dx.
+        map.insert(
+            Operation::In {},
+            vec![
+                InstructionCost::new(REGISTER, IMMEDIATE, 10, 0, false),
+                InstructionCost::new(REGISTER, REGISTER, 8, 0, false),
+            ],
+        );
+
+        // OUT.
+        map.insert(
+            Operation::Out {},
+            vec![
+                InstructionCost::new(IMMEDIATE, REGISTER, 10, 0, false),
+                InstructionCost::new(REGISTER, REGISTER, 8, 0, false),
+            ],
+        );
+
+        // DIV and IDIV. Only the word forms, and the timings vary with the operands so we take
+        // the lowest.
+        map.insert(
+            Operation::Div {},
+            vec![
+                InstructionCost::new(REGISTER, NONE, 144, 0, false),
+                InstructionCost::new(MEMORY, NONE, 150, 1, true),
+            ],
+        );
+        map.insert(
+            Operation::Idiv {},
+            vec![
+                InstructionCost::new(REGISTER, NONE, 165, 0, false),
+                InstructionCost::new(MEMORY, NONE, 171, 1, true),
+            ],
+        );
+
+        // Interrupts. INTO costs 53 instead when it is taken.
+        map.insert(
+            Operation::Int {},
+            vec![InstructionCost::new(IMMEDIATE, NONE, 51, 0, false)],
+        );
+        map.insert(Operation::Int3 {}, vec![InstructionCost::new(NONE, NONE, 52, 0, false)]);
+        map.insert(Operation::Into {}, vec![InstructionCost::new(NONE, NONE, 4, 0, false)]);
+        map.insert(Operation::Iret {}, vec![InstructionCost::new(NONE, NONE, 24, 0, false)]);
+        map.insert(Operation::Cli {}, vec![InstructionCost::new(NONE, NONE, 2, 0, false)]);
+        map.insert(Operation::Sti {}, vec![InstructionCost::new(NONE, NONE, 2, 0, false)]);
+
         Mutex::new(map)
     });
 
@@ -153,3 +203,66 @@ pub(super) fn resolve_eac_cost(eac: &EAC) -> usize {
         EAC::DirectAccess(_) => 6,
     }
 }
+
+// Cycle cost of an instruction that can be known without running it.
+// Whether a memory operand lands on an odd address is only known at runtime, so the transfer
+// penalty is not part of the estimate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleEstimate {
+    pub base: usize,
+    pub eac: usize,
+    // Memory transfers, each 4 cycles more when a word lands on an odd address.
+    pub transfers: usize,
+}
+
+impl CycleEstimate {
+    pub fn total(&self) -> usize {
+        self.base + self.eac
+    }
+}
+
+impl std::fmt::Display for CycleEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.eac == 0 {
+            return write!(f, "{} cycles", self.total());
+        }
+
+        write!(f, "{} cycles ({}+{}ea)", self.total(), self.base, self.eac)
+    }
+}
+
+// Returns None for operations we don't have timings for.
+pub fn estimate_cycles(instruction: &Instruction) -> Option<CycleEstimate> {
+    let map = COST_MAP.lock().unwrap();
+    let instruction_costs = map.get(&instruction.operation)?;
+
+    // The accumulator forms come first in the table, so the first match is the cheapest encoding.
+    let instruction_cost = instruction_costs.iter().find(|c| c.matches(instruction))?;
+
+    let mut eac = 0;
+    if instruction_cost.eac_cost {
+        if let Operand::EAC(e) = &instruction.dst {
+            eac = resolve_eac_cost(e);
+        } else if let Operand::EAC(e) = &instruction.src {
+            eac = resolve_eac_cost(e);
+        }
+    }
+
+    Some(CycleEstimate {
+        base: instruction_cost.base_cost as usize,
+        eac,
+        transfers: instruction_cost.transfers as usize,
+    })
+}
+
+// Cycles of a short jump when it is taken and when it is not.
+pub fn jump_cycles(jump: &Jump) -> (usize, usize) {
+    match jump {
+        Jump::JMP => (15, 15),
+        Jump::JCXZ => (18, 6),
+        Jump::LOOP => (17, 5),
+        Jump::LOOPZ => (18, 6),
+        Jump::LOOPNZ => (19, 5),
+        _ => (16, 4),
+    }
+}
@@ -0,0 +1,348 @@
+use super::instructions::*;
+use super::labels::*;
+use super::registers::*;
+use super::syntax::*;
+use log::debug;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+// Result of following the control flow of a program from its entry points.
+// Every byte of the program ends up either in a decoded instruction or in a data block.
+#[derive(Debug, Default)]
+pub struct Traversal {
+    // Sorted by address.
+    pub instructions: Vec<Instruction>,
+    // Sorted by start address.
+    pub blocks: Vec<BasicBlock>,
+    // Bytes that were never reached, sorted by address.
+    pub data: Vec<DataBlock>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    // One past the last byte of the block.
+    pub end: usize,
+    // Indices into |Traversal::instructions|.
+    pub instructions: Range<usize>,
+    // Start addresses of the blocks control can flow into.
+    pub successors: Vec<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq, serde::Serialize)]
+pub struct DataBlock {
+    pub address: usize,
+    pub bytes: Vec<u8>,
+}
+
+// Decodes only what is reachable from |entry_points|. Unlike |disassemble|, this never fails:
+// anything that cannot be decoded (or is never reached) is reported as data.
+pub fn disassemble_recursive(bytes: &[u8], entry_points: &[usize]) -> Traversal {
+    let mut decoded: BTreeMap<usize, Instruction> = BTreeMap::new();
+    let mut covered = vec![false; bytes.len()];
+    let mut pending: Vec<usize> = entry_points.iter().rev().copied().collect();
+
+    while let Some(mut address) = pending.pop() {
+        loop {
+            if address >= bytes.len() || covered[address] {
+                break;
+            }
+
+            let mut instruction = match Instruction::decode(&bytes[address..]) {
+                Ok(instruction) => instruction,
+                Err(e) => {
+                    debug!("Stopping traversal at {:04X}: {}", address, e);
+                    break;
+                }
+            };
+
+            // We don't allow instructions to overlap. The first decoding wins.
+            let range = address..(address + instruction.len());
+            if covered[range.clone()].iter().any(|c| *c) {
+                debug!("Stopping traversal at {:04X}: overlaps decoded code", address);
+                break;
+            }
+            covered[range].fill(true);
+
+            instruction.address = address;
+            let falls_through = falls_through(&instruction);
+            if let Some(target) = instruction.jump_target() {
+                pending.push(target);
+            }
+
+            address += instruction.len();
+            decoded.insert(instruction.address, instruction);
+
+            if !falls_through {
+                break;
+            }
+        }
+    }
+
+    let instructions: Vec<Instruction> = decoded.into_values().collect();
+    let blocks = build_blocks(&instructions, entry_points);
+    let data = collect_data(bytes, &covered);
+
+    Traversal {
+        instructions,
+        blocks,
+        data,
+    }
+}
+
+impl Traversal {
+    // Prints the reachable code with labels on the jump targets and the rest of the bytes as "db".
+    pub fn to_asm(&self) -> String {
+        to_asm_with_data(&self.instructions, &self.data, &Nasm)
+    }
+
+    // Prints one line per basic block with the blocks it can flow into.
+    pub fn cfg_string(&self) -> String {
+        let mut string = String::new();
+        for block in &self.blocks {
+            let successors: Vec<String> = block
+                .successors
+                .iter()
+                .map(|s| format!("{:04X}", s))
+                .collect();
+            string.push_str(&format!(
+                "{:04X}..{:04X} ({} instructions) -> [{}]\n",
+                block.start,
+                block.end,
+                block.instructions.len(),
+                successors.join(", "),
+            ));
+        }
+        string
+    }
+
+    pub fn find_instruction(&self, address: usize) -> Option<&Instruction> {
+        self.instructions
+            .binary_search_by_key(&address, |i| i.address)
+            .ok()
+            .map(|index| &self.instructions[index])
+    }
+}
+
+// Interleaves instructions and data blocks by address. Both slices must be sorted by address.
+// Jump targets that land on an instruction are referred by label, the rest keep the $ notation.
+pub fn to_asm_with_data(
+    instructions: &[Instruction],
+    data: &[DataBlock],
+    formatter: &dyn SyntaxFormatter,
+) -> String {
+    let labels: BTreeSet<usize> = instructions
+        .iter()
+        .filter_map(Instruction::jump_target)
+        .filter(|target| {
+            instructions
+                .binary_search_by_key(target, |i| i.address)
+                .is_ok()
+        })
+        .collect();
+
+    let mut string = format!("{}\n\n", formatter.header());
+
+    let mut instructions = instructions.iter().peekable();
+    let mut data = data.iter().peekable();
+    loop {
+        let next_instruction = instructions.peek().map(|i| i.address);
+        let next_data = data.peek().map(|d| d.address);
+
+        let take_instruction = match (next_instruction, next_data) {
+            (Some(i), Some(d)) => i < d,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => break,
+        };
+
+        if take_instruction {
+            let instruction = instructions.next().unwrap();
+            if labels.contains(&instruction.address) {
+                string.push_str(&formatter.label(&label_name(instruction.address)));
+                string.push('\n');
+            }
+            string.push_str(&format_instruction(instruction, &labels, formatter));
+            string.push('\n');
+        } else {
+            let block = data.next().unwrap();
+            for chunk in block.bytes.chunks(DB_BYTES_PER_LINE) {
+                string.push_str(&formatter.data(chunk));
+                string.push('\n');
+            }
+        }
+    }
+
+    string
+}
+
+// HELPERS -----------------------------------------------------------------------------------------
+
+const DB_BYTES_PER_LINE: usize = 8;
+
+fn falls_through(instruction: &Instruction) -> bool {
+    match &instruction.operation {
+        Operation::Jump(jump) => jump.jump != Jump::JMP,
+        _ => true,
+    }
+}
+
+fn build_blocks(instructions: &[Instruction], entry_points: &[usize]) -> Vec<BasicBlock> {
+    let decoded: BTreeSet<usize> = instructions.iter().map(|i| i.address).collect();
+
+    // Leaders are the instructions that start a basic block.
+    let mut leaders: BTreeSet<usize> = entry_points.iter().copied().collect();
+    for instruction in instructions {
+        if let Some(target) = instruction.jump_target() {
+            leaders.insert(target);
+            leaders.insert(instruction.address + instruction.len());
+        }
+    }
+
+    let mut blocks: Vec<BasicBlock> = vec![];
+    for (index, instruction) in instructions.iter().enumerate() {
+        let continues_block = match blocks.last() {
+            Some(block) => {
+                block.end == instruction.address && !leaders.contains(&instruction.address)
+            }
+            None => false,
+        };
+
+        if continues_block {
+            let block = blocks.last_mut().unwrap();
+            block.end += instruction.len();
+            block.instructions.end = index + 1;
+        } else {
+            blocks.push(BasicBlock {
+                start: instruction.address,
+                end: instruction.address + instruction.len(),
+                instructions: index..(index + 1),
+                successors: vec![],
+            });
+        }
+    }
+
+    for block in &mut blocks {
+        let last = &instructions[block.instructions.end - 1];
+
+        let mut successors = vec![];
+        if let Some(target) = last.jump_target() {
+            successors.push(target);
+        }
+        if falls_through(last) {
+            successors.push(block.end);
+        }
+
+        successors.retain(|s| decoded.contains(s));
+        successors.dedup();
+        block.successors = successors;
+    }
+
+    blocks
+}
+
+fn collect_data(bytes: &[u8], covered: &[bool]) -> Vec<DataBlock> {
+    let mut data: Vec<DataBlock> = vec![];
+    for (address, byte) in bytes.iter().enumerate() {
+        if covered[address] {
+            continue;
+        }
+
+        match data.last_mut() {
+            Some(block) if block.address + block.bytes.len() == address => block.bytes.push(*byte),
+            _ => data.push(DataBlock {
+                address,
+                bytes: vec![*byte],
+            }),
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skips_embedded_data() {
+        // jmp over
+        // db 0xFF, 0xFF
+        // over:
+        // mov cx, 3
+        let bytes = [0xEB, 0x02, 0xFF, 0xFF, 0xB9, 0x03, 0x00];
+        let traversal = disassemble_recursive(&bytes, &[0]);
+
+        assert_eq!(traversal.instructions.len(), 2);
+        assert_eq!(
+            traversal.data,
+            vec![DataBlock {
+                address: 2,
+                bytes: vec![0xFF, 0xFF]
+            }]
+        );
+
+        assert_eq!(traversal.blocks.len(), 2);
+        assert_eq!(traversal.blocks[0].successors, vec![4]);
+        assert!(traversal.blocks[1].successors.is_empty());
+
+        assert_eq!(
+            traversal.to_asm(),
+            "bits 16\n\njmp label_0004\ndb 0xFF, 0xFF\nlabel_0004:\nmov cx, 3\n"
+        );
+    }
+
+    #[test]
+    fn test_loop_blocks() {
+        // mov cx, 3
+        // top:
+        // sub cx, 1
+        // jnz top
+        // mov dx, 1
+        let bytes = [
+            0xB9, 0x03, 0x00, 0x83, 0xE9, 0x01, 0x75, 0xFB, 0xBA, 0x01, 0x00,
+        ];
+        let traversal = disassemble_recursive(&bytes, &[0]);
+
+        assert!(traversal.data.is_empty());
+        assert_eq!(
+            traversal.blocks,
+            vec![
+                BasicBlock {
+                    start: 0,
+                    end: 3,
+                    instructions: 0..1,
+                    successors: vec![3],
+                },
+                BasicBlock {
+                    start: 3,
+                    end: 8,
+                    instructions: 1..3,
+                    successors: vec![3, 8],
+                },
+                BasicBlock {
+                    start: 8,
+                    end: 11,
+                    instructions: 3..4,
+                    successors: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_undecodable_bytes_become_data() {
+        // mov cx, 3 followed by an unsupported opcode.
+        let bytes = [0xB9, 0x03, 0x00, 0xF4, 0x90];
+        let traversal = disassemble_recursive(&bytes, &[0]);
+
+        assert_eq!(traversal.instructions.len(), 1);
+        assert_eq!(
+            traversal.data,
+            vec![DataBlock {
+                address: 3,
+                bytes: vec![0xF4, 0x90]
+            }]
+        );
+        assert_eq!(traversal.to_asm(), "bits 16\n\nmov cx, 3\ndb 0xF4, 0x90\n");
+    }
+}
@@ -0,0 +1,138 @@
+// CGA text mode: 80x25 cells at B800:0000, each a character byte followed by an attribute byte.
+pub const TEXT_BUFFER_ADDRESS: usize = 0xB8000;
+pub const TEXT_COLUMNS: usize = 80;
+pub const TEXT_ROWS: usize = 25;
+
+// The attribute is blink (or bright background), background and foreground, in that order:
+// BRRRFFFF. The colors are in CGA order: black, blue, green, cyan, red, magenta, brown, gray.
+const BLINK: u8 = 0b1000_0000;
+
+// CGA color -> ANSI color. ANSI has red and blue swapped, and so cyan and brown too.
+const ANSI_COLORS: [u8; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
+
+// Code page 437, what the CGA character ROM draws. 0x20 to 0x7E are plain ASCII.
+#[rustfmt::skip]
+const CP437_LOW: [char; 32] = [
+    ' ', '☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼',
+    '►', '◄', '↕', '‼', '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲', '▼',
+];
+
+#[rustfmt::skip]
+const CP437_HIGH: [char; 129] = [
+    '⌂',
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', ' ',
+];
+
+pub fn cp437(byte: u8) -> char {
+    match byte {
+        0x00..=0x1F => CP437_LOW[byte as usize],
+        0x20..=0x7E => byte as char,
+        _ => CP437_HIGH[(byte - 0x7F) as usize],
+    }
+}
+
+// Renders the text buffer in |memory| as one line per row. Without colors trailing spaces are
+// dropped. With colors every attribute change becomes an ANSI escape, and each row ends with a
+// reset.
+pub fn render_text_buffer(memory: &[u8], colors: bool) -> String {
+    let mut result = String::new();
+
+    for row in 0..TEXT_ROWS {
+        let start = TEXT_BUFFER_ADDRESS + row * TEXT_COLUMNS * 2;
+        let Some(cells) = memory.get(start..start + TEXT_COLUMNS * 2) else {
+            break;
+        };
+
+        let mut line = String::new();
+        let mut attribute = None;
+        for cell in cells.chunks_exact(2) {
+            if colors && attribute != Some(cell[1]) {
+                attribute = Some(cell[1]);
+                line.push_str(&ansi_escape(cell[1]));
+            }
+            line.push(cp437(cell[0]));
+        }
+
+        if colors {
+            line.push_str("\x1b[0m");
+        } else {
+            line.truncate(line.trim_end().len());
+        }
+
+        result.push_str(&line);
+        result.push('\n');
+    }
+
+    result
+}
+
+fn ansi_escape(attribute: u8) -> String {
+    let foreground = attribute & 0b1111;
+    let background = (attribute >> 4) & 0b111;
+
+    // Bright colors have their own range in ANSI.
+    let foreground = match foreground {
+        0..=7 => 30 + ANSI_COLORS[foreground as usize],
+        _ => 90 + ANSI_COLORS[(foreground - 8) as usize],
+    };
+    let background = 40 + ANSI_COLORS[background as usize];
+
+    if attribute & BLINK != 0 {
+        format!("\x1b[0;5;{};{}m", foreground, background)
+    } else {
+        format!("\x1b[0;{};{}m", foreground, background)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory_with(row: usize, column: usize, cells: &[(u8, u8)]) -> Vec<u8> {
+        let mut memory = vec![0; 1024 * 1024];
+        let start = TEXT_BUFFER_ADDRESS + (row * TEXT_COLUMNS + column) * 2;
+        for (i, (character, attribute)) in cells.iter().enumerate() {
+            memory[start + i * 2] = *character;
+            memory[start + i * 2 + 1] = *attribute;
+        }
+        memory
+    }
+
+    #[test]
+    fn test_plain() {
+        let memory = memory_with(1, 2, &[(b'H', 0x07), (b'i', 0x07), (0xDB, 0x07), (0x03, 0x04)]);
+        let screen = render_text_buffer(&memory, false);
+
+        let lines: Vec<&str> = screen.lines().collect();
+        assert_eq!(lines.len(), TEXT_ROWS);
+        assert_eq!(lines[0], "");
+        assert_eq!(lines[1], "  Hi█♥");
+    }
+
+    #[test]
+    fn test_colors() {
+        // Gray on black, then bright yellow on blue, blinking.
+        let memory = memory_with(0, 0, &[(b'a', 0x07), (b'b', 0x07), (b'c', 0x9E)]);
+        let screen = render_text_buffer(&memory, true);
+
+        let first = screen.lines().next().unwrap();
+        assert!(first.starts_with("\x1b[0;37;40mab\x1b[0;5;93;44mc\x1b[0;30;40m "));
+        assert!(first.ends_with("\x1b[0m"));
+    }
+
+    #[test]
+    fn test_code_page() {
+        assert_eq!(cp437(0), ' ');
+        assert_eq!(cp437(b'~'), '~');
+        assert_eq!(cp437(0x7F), '⌂');
+        assert_eq!(cp437(0xB0), '░');
+        assert_eq!(cp437(0xFF), ' ');
+    }
+}
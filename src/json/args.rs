@@ -4,6 +4,8 @@ use clap::{Parser, ValueEnum};
 pub enum JsonParser {
     Serde,
     Custom,
+    /// Reads the pairs one at a time instead of loading the whole file.
+    Stream,
 }
 
 #[derive(Debug, Parser)]
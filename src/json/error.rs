@@ -0,0 +1,148 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum JsonErrorKind {
+    #[error("Unexpected token: {0}")]
+    UnexpectedToken(String),
+
+    #[error("Unexpected end of input")]
+    UnexpectedEnd,
+
+    #[error("Unclosed string")]
+    UnclosedString,
+
+    #[error("Invalid string: {0}")]
+    InvalidString(String),
+
+    #[error("Bad number: {0}")]
+    BadNumber(String),
+
+    #[error("Nesting deeper than {0} levels")]
+    DepthExceeded(usize),
+
+    #[error("Trailing data after the value")]
+    TrailingData,
+
+    #[error("Token longer than the {0} byte buffer")]
+    TokenTooLong(usize),
+
+    #[error("Reading input: {0}")]
+    Io(String),
+}
+
+// Where parsing failed and why. Lines and columns start at 1, and columns count characters.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{kind} at line {line}, column {column}\n{snippet}")]
+pub struct JsonError {
+    pub kind: JsonErrorKind,
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    // The line of the error with a caret under the column.
+    //
+    // {"a": 1 "b": 2}
+    //         ^
+    pub snippet: String,
+}
+
+// Long lines (minified JSON is one) are cut to this many characters around the error.
+const SNIPPET_WIDTH: usize = 60;
+
+impl JsonError {
+    pub fn new(bytes: &[u8], offset: usize, kind: JsonErrorKind) -> Self {
+        let offset = offset.min(bytes.len());
+        let line_start = bytes[..offset]
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map_or(0, |i| i + 1);
+        let line_end = bytes[offset..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .map_or(bytes.len(), |i| offset + i);
+
+        let line = bytes[..line_start].iter().filter(|b| **b == b'\n').count() + 1;
+        let column = count_chars(&bytes[line_start..offset]) + 1;
+
+        JsonError {
+            kind,
+            offset,
+            line,
+            column,
+            snippet: snippet(&bytes[line_start..line_end], column - 1),
+        }
+    }
+
+    // For errors in a window of a bigger input, when we know where the window is in it.
+    pub(super) fn at(mut self, offset: usize, line: usize, column: usize) -> Self {
+        self.offset = offset;
+        self.line = line;
+        self.column = column;
+        self
+    }
+}
+
+// Continuation bytes of UTF-8 don't start a character.
+pub(super) fn count_chars(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .filter(|b| **b & 0b1100_0000 != 0b1000_0000)
+        .count()
+}
+
+fn snippet(line: &[u8], column: usize) -> String {
+    // Tabs and such would throw the caret off.
+    let chars: Vec<char> = String::from_utf8_lossy(line)
+        .trim_end_matches('\r')
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+
+    let start = column.saturating_sub(SNIPPET_WIDTH / 2);
+    let end = chars.len().min(start + SNIPPET_WIDTH);
+    let mut text: String = chars[start.min(end)..end].iter().collect();
+    let mut caret = column - start;
+    if start > 0 {
+        text.insert_str(0, "...");
+        caret += 3;
+    }
+    if end < chars.len() {
+        text.push_str("...");
+    }
+
+    format!("{}\n{}^", text, " ".repeat(caret))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position() {
+        let bytes = "{\n  \"é\": 1 2\n}".as_bytes();
+        let offset = bytes.iter().position(|b| *b == b'2').unwrap();
+
+        let e = JsonError::new(bytes, offset, JsonErrorKind::TrailingData);
+        assert_eq!((e.line, e.column), (2, 10));
+        assert_eq!(e.snippet, "  \"é\": 1 2\n         ^");
+        assert_eq!(
+            e.to_string(),
+            "Trailing data after the value at line 2, column 10\n  \"é\": 1 2\n         ^"
+        );
+
+        // The end of the input is one past the last character.
+        let e = JsonError::new(b"[1", 2, JsonErrorKind::UnexpectedEnd);
+        assert_eq!((e.line, e.column, e.snippet.as_str()), (1, 3, "[1\n  ^"));
+    }
+
+    #[test]
+    fn test_long_line() {
+        let mut bytes = vec![b'1'; 200];
+        bytes[100] = b'x';
+
+        let e = JsonError::new(&bytes, 100, JsonErrorKind::TrailingData);
+        assert_eq!(e.column, 101);
+        let (text, caret) = e.snippet.split_once('\n').unwrap();
+        assert!(text.starts_with("...") && text.ends_with("..."));
+        assert_eq!(text.chars().nth(caret.len() - 1), Some('x'));
+    }
+}
@@ -1,8 +1,11 @@
+use error::*;
 use parse::*;
-use std::io::ErrorKind::InvalidData;
+use std::borrow::Cow;
 
 pub mod args;
+pub mod error;
 mod parse;
+pub mod stream;
 
 #[allow(unused_imports)]
 use log::debug;
@@ -13,7 +16,8 @@ use crate::profile_function;
 pub enum JsonValue<'a> {
     Array(Array<'a>),
     Object(Object<'a>),
-    String(&'a str),
+    // Borrowed from the input, unless it had escapes.
+    String(Cow<'a, str>),
     Number(f64),
     Bool(bool),
     Null,
@@ -26,7 +30,7 @@ pub struct Array<'a> {
 
 #[derive(Debug)]
 pub struct Object<'a> {
-    pub pairs: Vec<(&'a str, JsonValue<'a>)>,
+    pub pairs: Vec<(Cow<'a, str>, JsonValue<'a>)>,
 }
 
 impl<'a> Object<'a> {
@@ -44,38 +48,36 @@ impl<'a> Object<'a> {
     pub fn keys(&self) -> Vec<&str> {
         let mut keys = Vec::with_capacity(self.pairs.len());
         for (key, _) in &self.pairs {
-            keys.push(*key);
+            keys.push(key.as_ref());
         }
 
         keys
     }
 }
 
-pub fn parse(bytes: &[u8]) -> Result<JsonValue, std::io::Error> {
-    profile_function!();
+pub fn parse(bytes: &[u8]) -> Result<JsonValue<'_>, JsonError> {
+    parse_with(bytes, false)
+}
 
-    if bytes.is_empty() {
-        return Err(std::io::Error::new(InvalidData, "Empty data"));
-    }
+// Like |parse|, but only accepts what RFC 8259 does. |parse| also lets trailing commas through.
+pub fn parse_strict(bytes: &[u8]) -> Result<JsonValue<'_>, JsonError> {
+    parse_with(bytes, true)
+}
+
+fn parse_with(bytes: &[u8], strict: bool) -> Result<JsonValue<'_>, JsonError> {
+    profile_function!();
 
     let state = ParseState {
         index: 0,
         line: 0,
         char: 0,
+        strict,
+        token: 0,
+        depth: 0,
     };
 
-    let result = parse_value(bytes, state);
-
-    let output: ParseOutput;
-    match result {
-        Ok(o) => output = o,
-        Err(e) => {
-            return Err(std::io::Error::new(
-                InvalidData,
-                format!("line: {}, char: {} -> {}", e.state.line + 1, e.state.char + 1, e.e,),
-            ));
-        }
+    match parse_document(bytes, state) {
+        Ok(output) => Ok(output.value),
+        Err(e) => Err(JsonError::new(bytes, e.offset, e.kind)),
     }
-
-    Ok(output.value)
 }
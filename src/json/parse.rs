@@ -5,15 +5,26 @@ pub(super) struct ParseState {
     pub(super) index: usize,
     pub(super) line: usize,
     pub(super) char: usize,
+    // Only accept what RFC 8259 does. Otherwise trailing commas are allowed.
+    pub(super) strict: bool,
+    // Where the last token started, which is where errors about it point to.
+    pub(super) token: usize,
+    // How many arrays and objects we are in.
+    pub(super) depth: usize,
 }
 
+// Deeper documents are rejected instead of running out of stack.
+pub(super) const MAX_DEPTH: usize = 128;
+
 impl ParseState {
-    #[allow(dead_code)]
-    fn new(index: usize) -> Self {
+    pub(super) fn new(index: usize) -> Self {
         ParseState {
             index,
             line: 0,
             char: 0,
+            strict: false,
+            token: index,
+            depth: 0,
         }
     }
 }
@@ -26,7 +37,6 @@ impl std::fmt::Display for ParseState {
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token<'a> {
-    Invalid,
     EOF,
     LeftBracket,  // [
     RightBracket, // ]
@@ -34,7 +44,7 @@ pub enum Token<'a> {
     RightBrace,   // }
     Colon,        // :
     Comma,        // ,
-    String(&'a str),
+    String(Cow<'a, str>),
     Number(f64),
     Bool(bool),
     Null,
@@ -46,24 +56,73 @@ pub(super) struct ParseOutput<'a> {
     pub(super) state: ParseState,
 }
 
+impl std::fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::EOF => write!(f, "end of input"),
+            Token::LeftBracket => write!(f, "'['"),
+            Token::RightBracket => write!(f, "']'"),
+            Token::LeftBrace => write!(f, "'{{'"),
+            Token::RightBrace => write!(f, "'}}'"),
+            Token::Colon => write!(f, "':'"),
+            Token::Comma => write!(f, "','"),
+            Token::String(_) => write!(f, "string"),
+            Token::Number(_) => write!(f, "number"),
+            Token::Bool(b) => write!(f, "{}", b),
+            Token::Null => write!(f, "null"),
+        }
+    }
+}
+
+// Turned into a |JsonError| once we are back at the top, where the whole input is at hand.
 #[derive(Debug)]
 pub(super) struct Error {
-    pub(super) state: ParseState,
-    pub(super) e: std::io::Error,
+    pub(super) offset: usize,
+    pub(super) kind: JsonErrorKind,
 }
 
 impl Error {
-    fn new<E>(state: ParseState, e: E) -> Self
-    where
-        E: Into<Box<dyn std::error::Error + Send + Sync>>,
-    {
+    // At the byte being parsed.
+    fn new(state: &ParseState, kind: JsonErrorKind) -> Self {
+        Error {
+            offset: state.index,
+            kind,
+        }
+    }
+
+    // At the start of the last token.
+    fn at_token(state: &ParseState, kind: JsonErrorKind) -> Self {
         Error {
-            state,
-            e: std::io::Error::new(InvalidData, e),
+            offset: state.token,
+            kind,
+        }
+    }
+
+    fn unexpected(state: &ParseState, token: &Token, expected: &str) -> Self {
+        match token {
+            Token::EOF => Error::new(state, JsonErrorKind::UnexpectedEnd),
+            _ => Error::at_token(
+                state,
+                JsonErrorKind::UnexpectedToken(format!("expected {}, found {}", expected, token)),
+            ),
         }
     }
 }
 
+// The whole input is one value, with nothing but whitespace after it.
+pub(super) fn parse_document(bytes: &[u8], state: ParseState) -> Result<ParseOutput<'_>, Error> {
+    let output = parse_value(bytes, state)?;
+    // Whatever is after the value is trailing data, even when it doesn't make a valid token.
+    match parse_token(bytes, output.state.clone()) {
+        Ok((Token::EOF, _)) => Ok(output),
+        Ok((_, state)) => Err(Error::at_token(&state, JsonErrorKind::TrailingData)),
+        Err(e) => Err(Error {
+            kind: JsonErrorKind::TrailingData,
+            ..e
+        }),
+    }
+}
+
 pub(super) fn parse_value(bytes: &[u8], state: ParseState) -> Result<ParseOutput, Error> {
     let (token, state) = parse_token(bytes, state)?;
     parse_value_internal(bytes, token, state)
@@ -105,7 +164,7 @@ fn parse_value_internal<'a>(
         _ => (),
     }
 
-    Err(Error::new(state, format!("Unexpected token {:?}", first_token)))
+    Err(Error::unexpected(&state, &first_token, "a value"))
 }
 
 fn parse_array(bytes: &[u8], mut state: ParseState) -> Result<ParseOutput, Error> {
@@ -113,30 +172,36 @@ fn parse_array(bytes: &[u8], mut state: ParseState) -> Result<ParseOutput, Error
 
     // debug!("--> Parsing array at {}", state);
     let mut values = vec![];
+    enter(&mut state)?;
 
-    let prev_token = Token::Invalid;
     let mut token;
     loop {
         (token, state) = parse_token(bytes, state)?;
-        match token {
-            Token::RightBracket => break,
-            Token::Comma => {
-                if matches!(prev_token, Token::Comma) {
-                    return Err(Error::new(state, "Double comma"));
-                }
+
+        // Every value but the first one comes after a comma.
+        if !values.is_empty() {
+            match token {
+                Token::RightBracket => break,
+                Token::Comma => (token, state) = parse_token(bytes, state)?,
+                _ => return Err(Error::unexpected(&state, &token, "',' or ']'")),
             }
-            _ => {
-                let output = parse_value_internal(bytes, token, state)?;
-                values.push(output.value);
-                state = output.state;
+
+            if token == Token::RightBracket {
+                if state.strict {
+                    return Err(Error::unexpected(&state, &token, "a value"));
+                }
+                break;
             }
+        } else if token == Token::RightBracket {
+            break;
         }
-    }
 
-    if matches!(prev_token, Token::Comma) {
-        return Err(Error::new(state, "Comma at end of array"));
+        let output = parse_value_internal(bytes, token, state)?;
+        values.push(output.value);
+        state = output.state;
     }
 
+    state.depth -= 1;
     Ok(ParseOutput {
         value: JsonValue::Array(Array { values }),
         state,
@@ -148,34 +213,39 @@ fn parse_object(bytes: &[u8], mut state: ParseState) -> Result<ParseOutput, Erro
 
     // debug!("--> Parsing object at {}", state);
     let mut pairs = vec![];
+    enter(&mut state)?;
 
-    let prev_token = Token::Invalid;
     let mut token: Token;
     loop {
         (token, state) = parse_token(bytes, state)?;
 
-        let key: &str;
-        match token {
-            Token::Comma => {
-                if matches!(prev_token, Token::Comma) {
-                    return Err(Error::new(state, "Double Comma"));
+        // Every pair but the first one comes after a comma.
+        if !pairs.is_empty() {
+            match token {
+                Token::RightBrace => break,
+                Token::Comma => (token, state) = parse_token(bytes, state)?,
+                _ => return Err(Error::unexpected(&state, &token, "',' or '}'")),
+            }
+
+            if token == Token::RightBrace {
+                if state.strict {
+                    return Err(Error::unexpected(&state, &token, "a key"));
                 }
-                continue;
+                break;
             }
-            Token::RightBrace => break,
-            Token::String(k) => key = k,
-            _ => return Err(Error::new(state, "expected string as object key")),
+        } else if token == Token::RightBrace {
+            break;
         }
 
-        if key.is_empty() {
-            return Err(Error::new(state, "empty key"));
-        }
+        let Token::String(key) = token else {
+            return Err(Error::unexpected(&state, &token, "a key"));
+        };
 
         // TODO: Error check that key doesn't exist already.
 
         (token, state) = parse_token(bytes, state)?;
         let Token::Colon = token else {
-            return Err(Error::new(state, "expected colon after object key"))?;
+            return Err(Error::unexpected(&state, &token, "':'"));
         };
 
         let output = parse_value(bytes, state)?;
@@ -186,12 +256,21 @@ fn parse_object(bytes: &[u8], mut state: ParseState) -> Result<ParseOutput, Erro
         state = output.state;
     }
 
+    state.depth -= 1;
     Ok(ParseOutput {
         value: JsonValue::Object(Object { pairs }),
         state,
     })
 }
 
+fn enter(state: &mut ParseState) -> Result<(), Error> {
+    state.depth += 1;
+    if state.depth > MAX_DEPTH {
+        return Err(Error::at_token(state, JsonErrorKind::DepthExceeded(MAX_DEPTH)));
+    }
+    Ok(())
+}
+
 fn parse_token(bytes: &[u8], state: ParseState) -> Result<(Token, ParseState), Error> {
     let (token, state) = parse_token_internal(bytes, state)?;
 
@@ -201,13 +280,14 @@ fn parse_token(bytes: &[u8], state: ParseState) -> Result<(Token, ParseState), E
 }
 
 fn parse_token_internal(bytes: &[u8], mut state: ParseState) -> Result<(Token, ParseState), Error> {
-    if state.index >= bytes.len() {
-        state.index = bytes.len();
-        return Ok((Token::EOF, state));
-    }
-
     loop {
+        if state.index >= bytes.len() {
+            state.index = bytes.len();
+            return Ok((Token::EOF, state));
+        }
+
         let curr = bytes[state.index];
+        state.token = state.index;
         state.index += 1;
         state.char += 1;
         match curr {
@@ -230,19 +310,19 @@ fn parse_token_internal(bytes: &[u8], mut state: ParseState) -> Result<(Token, P
                 if let Some(state) = seek(bytes, state.clone(), b"rue") {
                     return Ok((Token::Bool(true), state));
                 }
-                return Err(Error::new(state, "Expected \"true\""));
+                return Err(unexpected_literal(bytes, &state, "true"));
             }
             b'f' => {
                 if let Some(state) = seek(bytes, state.clone(), b"alse") {
                     return Ok((Token::Bool(false), state));
                 }
-                return Err(Error::new(state, "Expected \"false\""));
+                return Err(unexpected_literal(bytes, &state, "false"));
             }
             b'n' => {
                 if let Some(state) = seek(bytes, state.clone(), b"ull") {
                     return Ok((Token::Null, state));
                 }
-                return Err(Error::new(state, "Expected \"null\""));
+                return Err(unexpected_literal(bytes, &state, "null"));
             }
             b'-' | b'0'..=b'9' => {
                 // We reparse the first number.
@@ -251,13 +331,41 @@ fn parse_token_internal(bytes: &[u8], mut state: ParseState) -> Result<(Token, P
                 return parse_number(bytes, state);
             }
             b':' => return Ok((Token::Colon, state)),
-            _ => return Err(Error::new(state, "Unexpected character")),
+            _ => {
+                let found = found_at(bytes, state.token);
+                let message = format!("expected a value, found {}", found);
+                return Err(Error::at_token(&state, JsonErrorKind::UnexpectedToken(message)));
+            }
         }
     }
 }
 
+fn unexpected_literal(bytes: &[u8], state: &ParseState, literal: &str) -> Error {
+    let message = format!("expected {}, found {}", literal, found_at(bytes, state.token));
+    Error::at_token(state, JsonErrorKind::UnexpectedToken(message))
+}
+
+// The word at |index| for error messages, or the character there if it doesn't start one.
+pub(super) fn found_at(bytes: &[u8], index: usize) -> String {
+    let rest = &bytes[index.min(bytes.len())..];
+    let word = rest
+        .iter()
+        .take(16)
+        .take_while(|b| b.is_ascii_alphanumeric())
+        .count();
+    let found = match word {
+        0 => String::from_utf8_lossy(&rest[..rest.len().min(4)])
+            .chars()
+            .next()
+            .map(String::from)
+            .unwrap_or_default(),
+        _ => String::from_utf8_lossy(&rest[..word]).into_owned(),
+    };
+    format!("'{}'", found)
+}
+
 fn seek(bytes: &[u8], mut state: ParseState, pattern: &[u8]) -> Option<ParseState> {
-    if state.index + pattern.len() >= bytes.len() {
+    if state.index + pattern.len() > bytes.len() {
         return None;
     }
 
@@ -276,84 +384,206 @@ fn seek(bytes: &[u8], mut state: ParseState, pattern: &[u8]) -> Option<ParseStat
 }
 
 // IMPORTANT: The index already considers parsing the initial double quote (one past it).
-fn parse_string(bytes: &[u8], mut state: ParseState) -> Result<(Token, ParseState), Error> {
-    let start_index: usize = state.index;
-    let mut curr: u8 = bytes[state.index];
+// The string is borrowed from |bytes| unless it has escapes, which need a copy to be resolved.
+pub(super) fn parse_string(
+    bytes: &[u8],
+    mut state: ParseState,
+) -> Result<(Token<'_>, ParseState), Error> {
+    let mut unescaped: Option<String> = None;
+    // Where the bytes not yet copied into |unescaped| start.
+    let mut run_start: usize = state.index;
 
-    // TODO: Support escaping.
-    let mut closed = false;
     loop {
+        let Some(&curr) = bytes.get(state.index) else {
+            return Err(Error::at_token(&state, JsonErrorKind::UnclosedString));
+        };
+
         match curr {
-            b'"' => {
-                closed = true;
-                break;
-            }
-            b'\n' => {
+            b'"' => break,
+            b'\\' => {
+                let run = to_str(&bytes[run_start..state.index], &state)?;
+                let string = unescaped.get_or_insert_with(String::new);
+                string.push_str(run);
+
                 state.index += 1;
-                state.line += 1;
-                state.char = 0;
+                state.char += 1;
+                string.push(parse_escape(bytes, &mut state)?);
+                run_start = state.index;
+            }
+            0x00..=0x1F => {
+                return Err(invalid_string(&state, "control character"));
             }
             _ => {
                 state.index += 1;
-                state.char += 1;
+                // Columns count characters, not the continuation bytes of UTF-8.
+                if curr & 0b1100_0000 != 0b1000_0000 {
+                    state.char += 1;
+                }
             }
         }
-
-        if state.index >= bytes.len() {
-            break;
-        }
-
-        curr = bytes[state.index];
     }
 
-    if !closed {
-        return Err(Error::new(state, "Unclosed string"));
-    }
-
-    let string: &str;
-    unsafe {
-        string = std::str::from_utf8_unchecked(&bytes[start_index..state.index]);
-    }
+    let run = to_str(&bytes[run_start..state.index], &state)?;
+    let string = match unescaped {
+        Some(mut string) => {
+            string.push_str(run);
+            Cow::Owned(string)
+        }
+        None => Cow::Borrowed(run),
+    };
 
     // We return the index *after* the last double quote.
     state.index += 1;
+    state.char += 1;
     Ok((Token::String(string), state))
 }
 
-fn parse_number(bytes: &[u8], mut state: ParseState) -> Result<(Token, ParseState), Error> {
-    // TODO: Maybe implement https://arxiv.org/abs/2101.11408 if we want to be mega fast?
+// The index is one past the backslash.
+fn parse_escape(bytes: &[u8], state: &mut ParseState) -> Result<char, Error> {
+    let Some(&curr) = bytes.get(state.index) else {
+        return Err(Error::at_token(state, JsonErrorKind::UnclosedString));
+    };
+    state.index += 1;
+    state.char += 1;
+
+    let c = match curr {
+        b'"' => '"',
+        b'\\' => '\\',
+        b'/' => '/',
+        b'b' => '\u{8}',
+        b'f' => '\u{c}',
+        b'n' => '\n',
+        b'r' => '\r',
+        b't' => '\t',
+        b'u' => return parse_unicode_escape(bytes, state),
+        _ => {
+            state.index -= 2;
+            return Err(invalid_string(state, "invalid escape"));
+        }
+    };
+    Ok(c)
+}
 
-    let start_index: usize = state.index;
+// "\uXXXX", or two of them for the UTF-16 surrogate pair of characters outside the BMP. The index
+// is one past the "u".
+fn parse_unicode_escape(bytes: &[u8], state: &mut ParseState) -> Result<char, Error> {
+    let first = parse_hex4(bytes, state)?;
 
-    let mut curr: u8 = bytes[state.index];
+    let code_point = match first {
+        0xD800..=0xDBFF => {
+            if bytes.get(state.index..state.index + 2) != Some(b"\\u") {
+                return Err(invalid_string(state, "unpaired surrogate"));
+            }
+            state.index += 2;
+            state.char += 2;
 
-    loop {
-        match curr {
-            b'-' | b'+' | b'e' | b'E' | b'.' | b'0'..=b'9' => {
-                state.index += 1;
-                state.char += 1;
+            let second = parse_hex4(bytes, state)?;
+            if !(0xDC00..=0xDFFF).contains(&second) {
+                return Err(invalid_string(state, "unpaired surrogate"));
             }
-            _ => break,
+            0x10000 + (((first as u32) - 0xD800) << 10) + ((second as u32) - 0xDC00)
         }
+        0xDC00..=0xDFFF => return Err(invalid_string(state, "unpaired surrogate")),
+        _ => first as u32,
+    };
 
-        if state.index >= bytes.len() {
-            break;
+    // Surrogates are the only code points that are not chars, and we handled them above.
+    char::from_u32(code_point).ok_or_else(|| invalid_string(state, "invalid code point"))
+}
+
+fn parse_hex4(bytes: &[u8], state: &mut ParseState) -> Result<u16, Error> {
+    let digits = bytes
+        .get(state.index..state.index + 4)
+        .filter(|digits| digits.iter().all(u8::is_ascii_hexdigit))
+        .ok_or_else(|| invalid_string(state, "expected 4 hex digits"))?;
+
+    // Only ASCII hex digits at this point.
+    let value = u16::from_str_radix(std::str::from_utf8(digits).unwrap(), 16).unwrap();
+    state.index += 4;
+    state.char += 4;
+    Ok(value)
+}
+
+fn to_str<'a>(bytes: &'a [u8], state: &ParseState) -> Result<&'a str, Error> {
+    std::str::from_utf8(bytes).map_err(|e| {
+        let offset = state.index - bytes.len() + e.valid_up_to();
+        let kind = JsonErrorKind::InvalidString(format!("invalid UTF-8: {}", e));
+        Error { offset, kind }
+    })
+}
+
+fn invalid_string(state: &ParseState, message: &str) -> Error {
+    Error::new(state, JsonErrorKind::InvalidString(message.to_string()))
+}
+
+// -?(0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?
+pub(super) fn parse_number(
+    bytes: &[u8],
+    mut state: ParseState,
+) -> Result<(Token<'_>, ParseState), Error> {
+    // TODO: Maybe implement https://arxiv.org/abs/2101.11408 if we want to be mega fast?
+
+    let start_index: usize = state.index;
+
+    skip_if(bytes, &mut state, |b| b == b'-');
+    match bytes.get(state.index) {
+        // No leading zeros.
+        Some(b'0') => state.index += 1,
+        Some(b'1'..=b'9') => {
+            skip_digits(bytes, &mut state);
         }
+        _ => return Err(bad_number(&state, "expected a digit")),
+    }
+
+    if skip_if(bytes, &mut state, |b| b == b'.') && skip_digits(bytes, &mut state) == 0 {
+        return Err(bad_number(&state, "expected a digit after the decimal point"));
+    }
 
-        curr = bytes[state.index];
+    if skip_if(bytes, &mut state, |b| b == b'e' || b == b'E') {
+        skip_if(bytes, &mut state, |b| b == b'+' || b == b'-');
+        if skip_digits(bytes, &mut state) == 0 {
+            return Err(bad_number(&state, "expected a digit in the exponent"));
+        }
     }
 
-    let string: &str;
-    unsafe {
-        string = std::str::from_utf8_unchecked(&bytes[start_index..state.index]);
+    // Anything that could continue a number means it was malformed ("01", "1.2.3", "1-2").
+    if let Some(b'-' | b'+' | b'e' | b'E' | b'.' | b'0'..=b'9') = bytes.get(state.index) {
+        return Err(bad_number(&state, "unexpected character"));
     }
+
+    state.char += state.index - start_index;
+
+    // Only ASCII at this point.
+    let string = std::str::from_utf8(&bytes[start_index..state.index]).unwrap();
     let num: f64 = string
         .parse()
-        .map_err(|e| Error::new(state.clone(), format!("Parsing float \"{}\": {}", string, e)))?;
+        .map_err(|e| bad_number(&state, &format!("\"{}\": {}", string, e)))?;
 
     Ok((Token::Number(num), state))
 }
 
+fn bad_number(state: &ParseState, message: &str) -> Error {
+    Error::new(state, JsonErrorKind::BadNumber(message.to_string()))
+}
+
+// Advances past the next byte if it matches.
+fn skip_if(bytes: &[u8], state: &mut ParseState, matches: impl Fn(u8) -> bool) -> bool {
+    match bytes.get(state.index) {
+        Some(b) if matches(*b) => {
+            state.index += 1;
+            true
+        }
+        _ => false,
+    }
+}
+
+// Returns how many digits were skipped.
+fn skip_digits(bytes: &[u8], state: &mut ParseState) -> usize {
+    let start = state.index;
+    while skip_if(bytes, state, |b| b.is_ascii_digit()) {}
+    state.index - start
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -399,6 +629,26 @@ mod tests {
             assert!(parse_number(bytes, ParseState::new(0)).is_err());
         }
 
+        #[test]
+        fn test_parse_grammar() {
+            for bytes in [
+                &b"01"[..],
+                b"-01",
+                b"1.",
+                b"1.e5",
+                b"1e",
+                b"1e+",
+                b"-",
+                b"1-2e+.",
+            ] {
+                assert!(parse_number(bytes, ParseState::new(0)).is_err(), "{:?}", bytes);
+            }
+
+            let (token, state) = parse_number(b"-0.5E+2]", ParseState::new(0)).unwrap();
+            assert_eq!(token, Token::Number(-50.0));
+            assert_eq!(state.index, 7);
+        }
+
         #[test]
         fn test_parse_at_offset() {
             let bytes = b"abc123.456   ";
@@ -418,7 +668,7 @@ mod tests {
             fn test_basic_string() {
                 let bytes = b"\"hello\"";
                 let (token, state) = parse_string(bytes, ParseState::new(1)).unwrap();
-                assert_eq!(token, Token::String("hello"));
+                assert_eq!(token, Token::String("hello".into()));
                 assert_eq!(state.index, 7);
             }
 
@@ -426,7 +676,7 @@ mod tests {
             fn test_empty_string() {
                 let bytes = b"\"\"";
                 let (token, state) = parse_string(bytes, ParseState::new(1)).unwrap();
-                assert_eq!(token, Token::String(""));
+                assert_eq!(token, Token::String("".into()));
                 assert_eq!(state.index, 2);
             }
 
@@ -434,7 +684,7 @@ mod tests {
             fn test_string_with_spaces() {
                 let bytes = b"\"hello world\"";
                 let (token, state) = parse_string(bytes, ParseState::new(1)).unwrap();
-                assert_eq!(token, Token::String("hello world"));
+                assert_eq!(token, Token::String("hello world".into()));
                 assert_eq!(state.index, 13);
             }
 
@@ -442,7 +692,7 @@ mod tests {
             fn test_string_at_offset() {
                 let bytes = b"abc\"hello\"";
                 let (token, state) = parse_string(bytes, ParseState::new(4)).unwrap();
-                assert_eq!(token, Token::String("hello"));
+                assert_eq!(token, Token::String("hello".into()));
                 assert_eq!(state.index, 10);
             }
 
@@ -451,16 +701,66 @@ mod tests {
                 let bytes = b"\"hello";
                 assert!(matches!(
                     parse_string(bytes, ParseState::new(1)),
-                    Err(e) if e.e.kind() == InvalidData
+                    Err(Error {
+                        kind: JsonErrorKind::UnclosedString,
+                        ..
+                    })
                 ));
             }
 
+            #[test]
+            fn test_escapes() {
+                let bytes = br#""a\"b\\c\/d\b\f\n\r\t""#;
+                let (token, state) = parse_string(bytes, ParseState::new(1)).unwrap();
+                assert_eq!(token, Token::String("a\"b\\c/d\u{8}\u{c}\n\r\t".into()));
+                assert_eq!(state.index, bytes.len());
+
+                let bytes = br#""\x""#;
+                assert!(parse_string(bytes, ParseState::new(1)).is_err());
+            }
+
+            #[test]
+            fn test_unicode() {
+                // Raw UTF-8, a BMP escape and a surrogate pair.
+                let bytes = r#""é \u00e9 \ud83d\ude00""#.as_bytes();
+                let (token, state) = parse_string(bytes, ParseState::new(1)).unwrap();
+                assert_eq!(token, Token::String("é é 😀".into()));
+                assert_eq!(state.index, bytes.len());
+                assert_eq!(state.char, 22);
+
+                for bytes in [
+                    &br#""\ud83d""#[..],
+                    br#""\ude00""#,
+                    br#""\ud83dx""#,
+                    br#""\u12""#,
+                ] {
+                    assert!(parse_string(bytes, ParseState::new(1)).is_err());
+                }
+
+                assert!(parse_string(b"\"\xff\"", ParseState::new(1)).is_err());
+            }
+
+            #[test]
+            fn test_control_characters() {
+                assert!(parse_string(b"\"a\nb\"", ParseState::new(1)).is_err());
+                assert!(parse_string(b"\"a\x01\"", ParseState::new(1)).is_err());
+            }
+
+            #[test]
+            fn test_zero_copy() {
+                let (token, _) = parse_string(b"\"plain\"", ParseState::new(1)).unwrap();
+                assert!(matches!(token, Token::String(Cow::Borrowed("plain"))));
+
+                let (token, _) = parse_string(br#""a\nb""#, ParseState::new(1)).unwrap();
+                assert!(matches!(token, Token::String(Cow::Owned(_))));
+            }
+
             #[test]
             fn test_empty_input() {
                 // This case is special since our code assumes we already processed the first ".
                 let bytes = b"\"";
                 let (token, state) = parse_string(bytes, ParseState::new(0)).unwrap();
-                assert_eq!(token, Token::String(""));
+                assert_eq!(token, Token::String("".into()));
                 assert_eq!(state.index, 1);
             }
         }
@@ -527,6 +827,68 @@ mod tests {
             }
         }
 
+        #[test]
+        fn test_escaped_keys_and_values() {
+            let bytes = r#"{"a\"b": "caf\u00e9", "plain": "é"}"#.as_bytes();
+            let JsonValue::Object(object) = parse(bytes).unwrap() else {
+                panic!("Expected object");
+            };
+
+            assert_eq!(object.keys(), vec!["a\"b", "plain"]);
+            assert!(matches!(object.get("a\"b"), Some(JsonValue::String(s)) if s == "café"));
+            assert!(matches!(object.get("plain"), Some(JsonValue::String(s)) if s == "é"));
+        }
+
+        #[test]
+        fn test_whole_document() {
+            // Literals used to need something after them.
+            assert!(matches!(parse(b"true"), Ok(JsonValue::Bool(true))));
+            assert!(matches!(parse(b"null "), Ok(JsonValue::Null)));
+
+            assert!(parse(b"[1] [2]").is_err());
+            assert!(parse(b"[1 2]").is_err());
+
+            // Trailing commas are only an error when strict.
+            assert!(parse(b"[1, 2,]").is_ok());
+            assert!(parse_strict(b"[1, 2,]").is_err());
+            assert!(parse_strict(br#"{"a": 1,}"#).is_err());
+        }
+
+        #[test]
+        fn test_error_kinds() {
+            use JsonErrorKind::*;
+
+            let cases: [(&[u8], JsonErrorKind, usize); 10] = [
+                (b"", UnexpectedEnd, 0),
+                (b"[1, 2", UnexpectedEnd, 5),
+                (b"{\"a\" 1}", UnexpectedToken("expected ':', found number".into()), 5),
+                (b"[1 2]", UnexpectedToken("expected ',' or ']', found number".into()), 3),
+                (b"[tru]", UnexpectedToken("expected true, found 'tru'".into()), 1),
+                (b"  @", UnexpectedToken("expected a value, found '@'".into()), 2),
+                (b"[\"abc", UnclosedString, 1),
+                (b"\"a\\xb\"", InvalidString("invalid escape".into()), 2),
+                (b"[1.e5]", BadNumber("expected a digit after the decimal point".into()), 3),
+                (b"[1] x", TrailingData, 4),
+            ];
+            for (bytes, kind, offset) in cases {
+                let e = parse(bytes).unwrap_err();
+                assert_eq!((e.kind, e.offset), (kind, offset), "{:?}", bytes);
+            }
+
+            let deep = "[".repeat(MAX_DEPTH + 1);
+            let e = parse(deep.as_bytes()).unwrap_err();
+            assert_eq!((e.kind, e.offset), (DepthExceeded(MAX_DEPTH), MAX_DEPTH));
+            let nested = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+            assert!(parse(nested.as_bytes()).is_ok());
+        }
+
+        #[test]
+        fn test_error_position() {
+            let e = parse(b"{\n  \"a\": [1,\n  \"b\": 2\n}").unwrap_err();
+            assert_eq!((e.line, e.column), (3, 6));
+            assert_eq!(e.snippet, "  \"b\": 2\n     ^");
+        }
+
         #[test]
         fn read_many_points() {
             let cargo_root = get_cargo_root().unwrap();
@@ -0,0 +1,589 @@
+use super::*;
+use std::io::{ErrorKind, Read};
+use std::ops::Range;
+
+// Pull parser for inputs that don't fit in memory. It reads through a fixed size buffer and hands
+// out one event at a time, so memory doesn't grow with the input, only with how deep it nests
+// (which is bounded by MAX_DEPTH). The price is that every token has to fit in the buffer.
+//
+// [{"x0": 1.5}]  ->  StartArray, StartObject, Key("x0"), Number(1.5), EndObject, EndArray
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event<'a> {
+    StartObject,
+    EndObject,
+    StartArray,
+    EndArray,
+    // Borrowed from the buffer, unless it had escapes. Only valid until the next event.
+    Key(Cow<'a, str>),
+    String(Cow<'a, str>),
+    Number(f64),
+    Bool(bool),
+    Null,
+}
+
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+pub struct StreamParser<R: Read> {
+    reader: R,
+    buffer: Box<[u8]>,
+    // The bytes read and not yet parsed are |buffer[pos..end]|.
+    pos: usize,
+    end: usize,
+    eof: bool,
+    // Where |buffer| starts in the whole input.
+    base: usize,
+    // Where the last token started in the whole input.
+    token: usize,
+    // Where the last string token is in |buffer|, quotes included.
+    string: Range<usize>,
+
+    strict: bool,
+    stack: Vec<Container>,
+    expect: Expect,
+
+    // For errors. The line is 1 based, and |line_start| is in the whole input.
+    line: usize,
+    line_start: usize,
+    // Characters of the current line that were in the buffer before |base|.
+    column_chars: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Container {
+    Array,
+    Object,
+}
+
+// What the grammar allows next.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Expect {
+    Value,
+    ArrayFirst,
+    ArrayComma,
+    ArrayAfterComma,
+    ObjectFirst,
+    ObjectComma,
+    ObjectAfterComma,
+    Colon,
+    Done,
+}
+
+enum Step {
+    Skip,
+    Done,
+    Event(Event<'static>),
+    // The payload is the last string token, which we only unescape once we hand it out.
+    Key,
+    String,
+}
+
+impl<R: Read> StreamParser<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_capacity(reader, DEFAULT_BUFFER_SIZE, false)
+    }
+
+    // Like |new|, but only accepts what RFC 8259 does, like |parse_strict|.
+    pub fn strict(reader: R) -> Self {
+        Self::with_capacity(reader, DEFAULT_BUFFER_SIZE, true)
+    }
+
+    pub fn with_capacity(reader: R, capacity: usize, strict: bool) -> Self {
+        StreamParser {
+            reader,
+            buffer: vec![0; capacity.max(1)].into_boxed_slice(),
+            pos: 0,
+            end: 0,
+            eof: false,
+            base: 0,
+            token: 0,
+            string: 0..0,
+            strict,
+            stack: vec![],
+            expect: Expect::Value,
+            line: 1,
+            line_start: 0,
+            column_chars: 0,
+        }
+    }
+
+    // How many arrays and objects we are in.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    // None once the whole document has been parsed. Don't call it again after an error.
+    pub fn next_event(&mut self) -> Result<Option<Event<'_>>, JsonError> {
+        loop {
+            let token = match self.next_token() {
+                // Whatever is after the value is trailing data, even when it isn't a valid token.
+                Err(e)
+                    if self.expect == Expect::Done && !matches!(e.kind, JsonErrorKind::Io(_)) =>
+                {
+                    return Err(JsonError {
+                        kind: JsonErrorKind::TrailingData,
+                        ..e
+                    });
+                }
+                result => result?,
+            };
+
+            match self.step(token)? {
+                Step::Skip => continue,
+                Step::Done => return Ok(None),
+                Step::Event(event) => return Ok(Some(event)),
+                Step::Key => return Ok(Some(Event::Key(self.string()?))),
+                Step::String => return Ok(Some(Event::String(self.string()?))),
+            }
+        }
+    }
+
+    // Advances the grammar with |token|.
+    fn step(&mut self, token: Token<'static>) -> Result<Step, JsonError> {
+        let step = match (self.expect, token) {
+            (Expect::Done, Token::EOF) => Step::Done,
+            (Expect::Done, _) => return Err(self.error(self.token, JsonErrorKind::TrailingData)),
+
+            (Expect::ArrayFirst | Expect::ArrayComma, Token::RightBracket) => {
+                self.end_container(Event::EndArray)
+            }
+            (Expect::ArrayAfterComma, Token::RightBracket) if !self.strict => {
+                self.end_container(Event::EndArray)
+            }
+            (Expect::ArrayComma, Token::Comma) => {
+                self.expect = Expect::ArrayAfterComma;
+                Step::Skip
+            }
+            (Expect::ArrayComma, token) => return Err(self.unexpected(&token, "',' or ']'")),
+
+            (Expect::ObjectFirst | Expect::ObjectComma, Token::RightBrace) => {
+                self.end_container(Event::EndObject)
+            }
+            (Expect::ObjectAfterComma, Token::RightBrace) if !self.strict => {
+                self.end_container(Event::EndObject)
+            }
+            (Expect::ObjectComma, Token::Comma) => {
+                self.expect = Expect::ObjectAfterComma;
+                Step::Skip
+            }
+            (Expect::ObjectComma, token) => return Err(self.unexpected(&token, "',' or '}'")),
+            (Expect::ObjectFirst | Expect::ObjectAfterComma, Token::String(_)) => {
+                self.expect = Expect::Colon;
+                Step::Key
+            }
+            (Expect::ObjectFirst | Expect::ObjectAfterComma, token) => {
+                return Err(self.unexpected(&token, "a key"))
+            }
+            (Expect::Colon, Token::Colon) => {
+                self.expect = Expect::Value;
+                Step::Skip
+            }
+            (Expect::Colon, token) => return Err(self.unexpected(&token, "':'")),
+
+            (Expect::Value | Expect::ArrayFirst | Expect::ArrayAfterComma, token) => {
+                self.value(token)?
+            }
+        };
+
+        Ok(step)
+    }
+
+    fn value(&mut self, token: Token<'static>) -> Result<Step, JsonError> {
+        let step = match token {
+            Token::LeftBracket => {
+                self.push(Container::Array)?;
+                self.expect = Expect::ArrayFirst;
+                return Ok(Step::Event(Event::StartArray));
+            }
+            Token::LeftBrace => {
+                self.push(Container::Object)?;
+                self.expect = Expect::ObjectFirst;
+                return Ok(Step::Event(Event::StartObject));
+            }
+            Token::String(_) => Step::String,
+            Token::Number(n) => Step::Event(Event::Number(n)),
+            Token::Bool(b) => Step::Event(Event::Bool(b)),
+            Token::Null => Step::Event(Event::Null),
+            token => return Err(self.unexpected(&token, "a value")),
+        };
+
+        self.after_value();
+        Ok(step)
+    }
+
+    fn push(&mut self, container: Container) -> Result<(), JsonError> {
+        if self.stack.len() >= MAX_DEPTH {
+            return Err(self.error(self.token, JsonErrorKind::DepthExceeded(MAX_DEPTH)));
+        }
+        self.stack.push(container);
+        Ok(())
+    }
+
+    fn end_container(&mut self, event: Event<'static>) -> Step {
+        self.stack.pop();
+        self.after_value();
+        Step::Event(event)
+    }
+
+    fn after_value(&mut self) {
+        self.expect = match self.stack.last() {
+            None => Expect::Done,
+            Some(Container::Array) => Expect::ArrayComma,
+            Some(Container::Object) => Expect::ObjectComma,
+        };
+    }
+
+    // TOKENS --------------------------------------------------------------------------------------
+
+    // Strings come back empty, their bytes are at |self.string|.
+    fn next_token(&mut self) -> Result<Token<'static>, JsonError> {
+        let more = self.skip_whitespace()?;
+        self.token = self.base + self.pos;
+        if !more {
+            return Ok(Token::EOF);
+        }
+
+        let token = match self.buffer[self.pos] {
+            b'[' => Token::LeftBracket,
+            b']' => Token::RightBracket,
+            b'{' => Token::LeftBrace,
+            b'}' => Token::RightBrace,
+            b',' => Token::Comma,
+            b':' => Token::Colon,
+            b'"' => return self.string_token(),
+            b't' => return self.literal(b"true", Token::Bool(true)),
+            b'f' => return self.literal(b"false", Token::Bool(false)),
+            b'n' => return self.literal(b"null", Token::Null),
+            b'-' | b'0'..=b'9' => return self.number_token(),
+            _ => {
+                let found = found_at(&self.buffer[..self.end], self.pos);
+                let message = format!("expected a value, found {}", found);
+                return Err(self.error(self.token, JsonErrorKind::UnexpectedToken(message)));
+            }
+        };
+
+        self.pos += 1;
+        Ok(token)
+    }
+
+    // Returns false at the end of the input.
+    fn skip_whitespace(&mut self) -> Result<bool, JsonError> {
+        loop {
+            if self.pos == self.end && !self.fill(1)? {
+                return Ok(false);
+            }
+
+            match self.buffer[self.pos] {
+                b' ' | b'\r' | b'\t' => self.pos += 1,
+                b'\n' => {
+                    self.pos += 1;
+                    self.line += 1;
+                    self.line_start = self.base + self.pos;
+                }
+                _ => return Ok(true),
+            }
+        }
+    }
+
+    // Only finds where the string ends. It is parsed by |string| when handed out.
+    fn string_token(&mut self) -> Result<Token<'static>, JsonError> {
+        let mut escaped = false;
+        let is_end = |b: u8| {
+            let end = !escaped && b == b'"';
+            escaped = !escaped && b == b'\\';
+            end
+        };
+
+        let Some(last) = self.scan(1, is_end)? else {
+            return Err(self.error(self.token, JsonErrorKind::UnclosedString));
+        };
+
+        self.string = self.pos..(self.pos + last + 1);
+        self.pos = self.string.end;
+        Ok(Token::String(Cow::Borrowed("")))
+    }
+
+    fn string(&self) -> Result<Cow<'_, str>, JsonError> {
+        // Skip the opening quote, like the tokenizer of |parse| does.
+        match parse_string(&self.buffer[self.string.clone()], ParseState::new(1)) {
+            Ok((Token::String(string), _)) => Ok(string),
+            Ok(_) => unreachable!("parse_string only returns strings"),
+            Err(e) => Err(self.error(self.token + e.offset, e.kind)),
+        }
+    }
+
+    fn number_token(&mut self) -> Result<Token<'static>, JsonError> {
+        // Everything that could be part of it, so that |parse_number| sees the malformed ones.
+        let is_end = |b: u8| !matches!(b, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9');
+        let len = match self.scan(0, is_end)? {
+            Some(len) => len,
+            None => self.end - self.pos,
+        };
+
+        let bytes = &self.buffer[self.pos..(self.pos + len)];
+        let number = match parse_number(bytes, ParseState::new(0)) {
+            Ok((Token::Number(n), _)) => n,
+            Ok(_) => unreachable!("parse_number only returns numbers"),
+            Err(e) => return Err(self.error(self.token + e.offset, e.kind)),
+        };
+
+        self.pos += len;
+        Ok(Token::Number(number))
+    }
+
+    fn literal(
+        &mut self,
+        literal: &[u8],
+        token: Token<'static>,
+    ) -> Result<Token<'static>, JsonError> {
+        if self.fill(literal.len())? && self.buffer[self.pos..self.end].starts_with(literal) {
+            self.pos += literal.len();
+            return Ok(token);
+        }
+
+        let expected = std::str::from_utf8(literal).unwrap();
+        let found = found_at(&self.buffer[..self.end], self.pos);
+        let message = format!("expected {}, found {}", expected, found);
+        Err(self.error(self.token, JsonErrorKind::UnexpectedToken(message)))
+    }
+
+    // BUFFER --------------------------------------------------------------------------------------
+
+    // How far after |pos| the first byte |is_end| accepts is, starting |skip| bytes in. Reads more
+    // as needed. None if the input ends before.
+    fn scan(
+        &mut self,
+        skip: usize,
+        mut is_end: impl FnMut(u8) -> bool,
+    ) -> Result<Option<usize>, JsonError> {
+        let mut len = skip;
+        loop {
+            while self.pos + len < self.end {
+                if is_end(self.buffer[self.pos + len]) {
+                    return Ok(Some(len));
+                }
+                len += 1;
+            }
+
+            if !self.fill(len + 1)? {
+                return Ok(None);
+            }
+        }
+    }
+
+    // Makes sure there are |need| bytes after |pos|. Returns false if the input ends first.
+    fn fill(&mut self, need: usize) -> Result<bool, JsonError> {
+        while self.end - self.pos < need {
+            if self.eof {
+                return Ok(false);
+            }
+            if need > self.buffer.len() {
+                let kind = JsonErrorKind::TokenTooLong(self.buffer.len());
+                return Err(self.error(self.base + self.pos, kind));
+            }
+
+            // Only when out of room, the parsed bytes are still good for error snippets.
+            if self.end == self.buffer.len() || self.pos + need > self.buffer.len() {
+                self.compact();
+            }
+
+            match self.reader.read(&mut self.buffer[self.end..]) {
+                Ok(0) => self.eof = true,
+                Ok(read) => self.end += read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    let kind = JsonErrorKind::Io(e.to_string());
+                    return Err(self.error(self.base + self.pos, kind));
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    // Moves the unparsed bytes to the start of the buffer, to make room for reading.
+    fn compact(&mut self) {
+        // We are about to lose the start of the line, so we keep how many characters it had.
+        self.column_chars = match self.line_start.checked_sub(self.base) {
+            Some(start) => count_chars(&self.buffer[start..self.pos]),
+            None => self.column_chars + count_chars(&self.buffer[..self.pos]),
+        };
+
+        self.buffer.copy_within(self.pos..self.end, 0);
+        self.base += self.pos;
+        self.end -= self.pos;
+        self.pos = 0;
+    }
+
+    // |offset| is in the whole input. The snippet only has what is still in the buffer.
+    fn error(&self, offset: usize, kind: JsonErrorKind) -> JsonError {
+        let local = (offset - self.base).min(self.end);
+        let (window, column) = match self.line_start.checked_sub(self.base) {
+            Some(start) => (start, count_chars(&self.buffer[start..local])),
+            None => (0, self.column_chars + count_chars(&self.buffer[..local])),
+        };
+
+        JsonError::new(&self.buffer[window..self.end], local - window, kind).at(
+            offset,
+            self.line,
+            column + 1,
+        )
+    }
+
+    fn unexpected(&self, token: &Token, expected: &str) -> JsonError {
+        match token {
+            Token::EOF => self.error(self.base + self.pos, JsonErrorKind::UnexpectedEnd),
+            _ => {
+                let message = format!("expected {}, found {}", expected, token);
+                self.error(self.token, JsonErrorKind::UnexpectedToken(message))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_cargo_root;
+
+    // Hands out one byte per read, to split every token.
+    struct OneByte<'a>(&'a [u8]);
+
+    impl Read for OneByte<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let Some((first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            buf[0] = *first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    fn events<R: Read>(mut parser: StreamParser<R>) -> Result<Vec<String>, JsonError> {
+        let mut events = vec![];
+        while let Some(event) = parser.next_event()? {
+            events.push(format!("{:?}", event));
+        }
+        Ok(events)
+    }
+
+    #[test]
+    fn test_events() {
+        let bytes = r#"{"a": [1, -2.5e1, "x\ny"], "bé": {"c": true, "d": null}, "e": []}"#;
+        let bytes = bytes.as_bytes();
+        let want = [
+            "StartObject",
+            "Key(\"a\")",
+            "StartArray",
+            "Number(1.0)",
+            "Number(-25.0)",
+            "String(\"x\\ny\")",
+            "EndArray",
+            "Key(\"bé\")",
+            "StartObject",
+            "Key(\"c\")",
+            "Bool(true)",
+            "Key(\"d\")",
+            "Null",
+            "EndObject",
+            "Key(\"e\")",
+            "StartArray",
+            "EndArray",
+            "EndObject",
+        ];
+
+        assert_eq!(events(StreamParser::new(bytes)).unwrap(), want);
+        // Every token split across reads, in a buffer that barely fits the longest one and the
+        // byte after it.
+        let parser = StreamParser::with_capacity(OneByte(bytes), 7, false);
+        assert_eq!(events(parser).unwrap(), want);
+    }
+
+    #[test]
+    fn test_scalars_and_whitespace() {
+        assert_eq!(events(StreamParser::new(&b" 12 \n"[..])).unwrap(), ["Number(12.0)"]);
+        assert_eq!(events(StreamParser::new(&b"null"[..])).unwrap(), ["Null"]);
+
+        // Trailing commas, only when not strict.
+        assert!(events(StreamParser::new(&b"[1, {\"a\": 2,},]"[..])).is_ok());
+        assert!(events(StreamParser::strict(&b"[1, 2,]"[..])).is_err());
+        assert!(events(StreamParser::strict(&b"{\"a\": 2,}"[..])).is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        use JsonErrorKind::*;
+
+        let cases: [(&[u8], JsonErrorKind, usize); 9] = [
+            (b"", UnexpectedEnd, 0),
+            (b"[1, 2", UnexpectedEnd, 5),
+            (b"{\"a\" 1}", UnexpectedToken("expected ':', found number".into()), 5),
+            (b"[tru]", UnexpectedToken("expected true, found 'tru'".into()), 1),
+            (b"[\"abc", UnclosedString, 1),
+            (b"[\"a\\xb\"]", InvalidString("invalid escape".into()), 3),
+            (b"[01]", BadNumber("unexpected character".into()), 2),
+            (b"[1] x", TrailingData, 4),
+            (b"[1] [", TrailingData, 4),
+        ];
+        for (bytes, kind, offset) in cases {
+            let e = events(StreamParser::with_capacity(OneByte(bytes), 8, false)).unwrap_err();
+            assert_eq!((e.kind, e.offset), (kind, offset), "{:?}", bytes);
+        }
+
+        let deep = "[".repeat(MAX_DEPTH + 1);
+        let e = events(StreamParser::new(deep.as_bytes())).unwrap_err();
+        assert_eq!((e.kind, e.offset), (DepthExceeded(MAX_DEPTH), MAX_DEPTH));
+
+        let e = events(StreamParser::with_capacity(&b"[\"0123456789\"]"[..], 8, false));
+        assert_eq!(e.unwrap_err().kind, TokenTooLong(8));
+    }
+
+    #[test]
+    fn test_error_position() {
+        // The start of the line is long gone from the buffer when we find the error.
+        let bytes = format!("[\n  {}2 3]", "1, ".repeat(100));
+        let parser = StreamParser::with_capacity(bytes.as_bytes(), 16, false);
+
+        let e = events(parser).unwrap_err();
+        assert_eq!((e.line, e.column, e.offset), (2, 305, 306));
+        let (text, caret) = e.snippet.split_once('\n').unwrap();
+        assert!(text.ends_with("2 3]"), "{}", e.snippet);
+        assert_eq!(text.chars().nth(caret.len() - 1), Some('3'));
+    }
+
+    #[test]
+    fn test_agrees_with_parse() {
+        let bytes = std::fs::read(
+            get_cargo_root()
+                .unwrap()
+                .join("extras/json/coords_100.json"),
+        );
+        let bytes = bytes.unwrap();
+
+        let mut numbers = vec![];
+        let mut parser = StreamParser::with_capacity(&bytes[..], 64, true);
+        while let Some(event) = parser.next_event().unwrap() {
+            if let Event::Number(n) = event {
+                numbers.push(n);
+            }
+        }
+
+        let JsonValue::Array(array) = parse(&bytes).unwrap() else {
+            panic!("Expected array");
+        };
+        let mut want = vec![];
+        for value in &array.values {
+            let JsonValue::Object(object) = value else {
+                panic!("Expected object");
+            };
+            for (_, value) in &object.pairs {
+                let JsonValue::Number(n) = value else {
+                    panic!("Expected number");
+                };
+                want.push(*n);
+            }
+        }
+
+        assert_eq!(numbers, want);
+    }
+}
@@ -1,20 +1,24 @@
 use super::get_cargo_root;
-use std::path::Path;
+use crate::intel8086::memory;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use tempfile::NamedTempFile;
 
 use std::io::{Error, ErrorKind};
 
+// Overrides where nasm is looked up.
+const NASM_ENV: &str = "NASM";
+
 pub fn run_nasm(output_dir: &Path, filepath: impl AsRef<Path>) -> Result<Vec<u8>, Error> {
-    let cargo_root = get_cargo_root()?;
-    let nasm = cargo_root.join("extras").join("nasm").join("nasm.exe");
+    let nasm = find_nasm()?;
 
     let temp_file = NamedTempFile::new_in(output_dir)?;
 
-    let output = Command::new(nasm)
+    let output = Command::new(&nasm)
         .args(["-o", temp_file.path().to_str().unwrap()])
         .arg(filepath.as_ref().as_os_str())
-        .output()?;
+        .output()
+        .map_err(|e| Error::new(e.kind(), format!("running {}: {}", nasm.display(), e)))?;
 
     if !output.status.success() {
         return Err(Error::new(
@@ -30,3 +34,126 @@ pub fn run_nasm(output_dir: &Path, filepath: impl AsRef<Path>) -> Result<Vec<u8>
     let bytes = std::fs::read(&temp_file)?;
     Ok(bytes)
 }
+
+// Returns the bytes of an assembled listing. If there is a precompiled "<listing>.bin" next to it
+// that was assembled from the listing as it is now, we use that, so nasm is only needed for
+// listings that changed since they were last assembled.
+pub fn assemble_listing(output_dir: &Path, listing: impl AsRef<Path>) -> Result<Vec<u8>, Error> {
+    let listing = listing.as_ref();
+    if is_listing_cache_fresh(listing)? {
+        return std::fs::read(cached_listing_path(listing));
+    }
+
+    run_nasm(output_dir, listing)
+}
+
+// Stores |bytes| as the precompiled bytes of |listing|, along with the hash of its source in
+// "<listing>.hash".
+pub fn write_listing_cache(listing: &Path, bytes: &[u8]) -> Result<(), Error> {
+    std::fs::write(cached_listing_path(listing), bytes)?;
+    std::fs::write(listing_hash_path(listing), format!("0x{:016x}\n", source_hash(listing)?))
+}
+
+// Whether "<listing>.bin" exists and its hash matches the listing.
+pub fn is_listing_cache_fresh(listing: &Path) -> Result<bool, Error> {
+    if !cached_listing_path(listing).exists() {
+        return Ok(false);
+    }
+    let Ok(stored) = std::fs::read_to_string(listing_hash_path(listing)) else {
+        return Ok(false);
+    };
+    Ok(stored.trim() == format!("0x{:016x}", source_hash(listing)?))
+}
+
+pub fn cached_listing_path(listing: &Path) -> PathBuf {
+    listing.with_extension("bin")
+}
+
+pub fn listing_hash_path(listing: &Path) -> PathBuf {
+    listing.with_extension("hash")
+}
+
+// Looks for nasm in this order:
+// 1. The path in the NASM environment variable.
+// 2. "nasm" in the PATH.
+// 3. The Windows binary bundled in extras/nasm.
+pub fn find_nasm() -> Result<PathBuf, Error> {
+    if let Some(path) = std::env::var_os(NASM_ENV) {
+        let path = PathBuf::from(path);
+        if !path.is_file() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("{} points to {}, which is not a file", NASM_ENV, path.display()),
+            ));
+        }
+        return Ok(path);
+    }
+
+    if let Some(paths) = std::env::var_os("PATH") {
+        let names: &[&str] = if cfg!(windows) {
+            &["nasm.exe", "nasm"]
+        } else {
+            &["nasm"]
+        };
+
+        for dir in std::env::split_paths(&paths) {
+            for name in names {
+                let candidate = dir.join(name);
+                if candidate.is_file() {
+                    return Ok(candidate);
+                }
+            }
+        }
+    }
+
+    if cfg!(windows) {
+        let bundled = get_cargo_root()?
+            .join("extras")
+            .join("nasm")
+            .join("nasm.exe");
+        if bundled.is_file() {
+            return Ok(bundled);
+        }
+    }
+
+    Err(Error::new(
+        ErrorKind::NotFound,
+        format!("nasm not found: install it or set {} to its path", NASM_ENV),
+    ))
+}
+
+// HELPERS -----------------------------------------------------------------------------------------
+
+// Line endings don't count, so a checkout with CRLF still uses the cache.
+fn source_hash(listing: &Path) -> Result<u64, Error> {
+    let mut source = std::fs::read(listing)?;
+    source.retain(|byte| *byte != b'\r');
+    Ok(memory::hash(&source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stale_cache_is_not_used() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let listing = dir.path().join("listing.asm");
+        std::fs::write(&listing, "bits 16\nmov cx, 3\n").unwrap();
+
+        let bytes = [0xB9, 0x03, 0x00];
+        write_listing_cache(&listing, &bytes).unwrap();
+        assert!(is_listing_cache_fresh(&listing).unwrap());
+        assert_eq!(assemble_listing(dir.path(), &listing).unwrap(), bytes);
+
+        // CRLF line endings are the same listing.
+        std::fs::write(&listing, "bits 16\r\nmov cx, 3\r\n").unwrap();
+        assert!(is_listing_cache_fresh(&listing).unwrap());
+
+        // Without nasm this fails, with it we get the new bytes. Never the old ones.
+        std::fs::write(&listing, "bits 16\nmov cx, 4\n").unwrap();
+        assert!(!is_listing_cache_fresh(&listing).unwrap());
+        let result = assemble_listing(dir.path(), &listing);
+        assert!(!matches!(result, Ok(got) if got == bytes));
+    }
+}
@@ -14,9 +14,6 @@ pub enum TestError {
 
     #[error("Wrong test result")]
     WrongResult,
-
-    #[error("Custom Error: {0}")]
-    CustomError(String)
 }
 
 impl TestError {
@@ -33,12 +30,6 @@ impl TestError {
             err: source,
         }
     }
-
-    pub(super) fn custom(msg: String) -> TestError {
-        TestError::CustomError {
-            0: msg,
-        }
-    }
 }
 
 
@@ -4,21 +4,41 @@ pub mod simulation;
 use computer_enhance_rust::{get_cargo_root, nasm::*};
 use error::TestError;
 use similar::{ChangeTag, TextDiff};
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use tempfile::TempDir;
 
 use computer_enhance_rust::intel8086;
 
+// Our own encoder has to give back the bytes we disassembled. Doesn't need nasm.
+pub fn run_encoder_test(listing_name: &str) -> Result<(), TestError> {
+    let temp_dir = TempDir::new().map_err(|e| TestError::io(String::from("TempDir"), e))?;
+    let listing = find_listing(listing_name)?;
+
+    let want_bytes = assemble_listing(temp_dir.path(), &listing)
+        .map_err(|e| TestError::io(listing.display().to_string(), e))?;
+    println!("WANT BYTES: {:02X?}", want_bytes);
+
+    let encoded_bytes = encode_all(&intel8086::disassemble(&want_bytes)?)?;
+    println!(" ENC BYTES: {:02X?}", encoded_bytes);
+
+    if want_bytes != encoded_bytes {
+        return Err(TestError::WrongResult);
+    }
+    Ok(())
+}
+
+// Our disassembly has to go back to the same bytes through both our encoder and nasm.
 pub fn run_nasm_test(listing_name: &str) -> Result<(), TestError> {
+    run_encoder_test(listing_name)?;
+
     // Create a temporary dir for this test.
     let temp_dir = TempDir::new().map_err(|e| TestError::io(String::from("TempDir"), e))?;
 
-    // Run nasm on the input file.
+    // Assemble the input file, or take its precompiled bytes.
     let listing = find_listing(listing_name)?;
 
-    let want_bytes = run_nasm(temp_dir.path(), &listing)
+    let want_bytes = assemble_listing(temp_dir.path(), &listing)
         .map_err(|e| TestError::io(listing.display().to_string(), e))?;
-    println!("WANT BYTES: {:02X?}", want_bytes);
 
     let got_instructions = intel8086::disassemble(&want_bytes)?;
     let got_asm = intel8086::to_asm(&got_instructions);
@@ -53,19 +73,101 @@ pub fn run_nasm_test(listing_name: &str) -> Result<(), TestError> {
         }
     }
 
-    assert!(false);
-    return Ok(());
+    Err(TestError::WrongResult)
+}
+
+// The precompiled bytes of a listing have to be what nasm produces for it.
+pub fn check_listing_cache(listing_name: &str) -> Result<(), TestError> {
+    let temp_dir = TempDir::new().map_err(|e| TestError::io(String::from("TempDir"), e))?;
+    let listing = find_listing(listing_name)?;
+
+    let cached = cached_listing_path(&listing);
+    let cached_bytes =
+        std::fs::read(&cached).map_err(|e| TestError::io(cached.display().to_string(), e))?;
+    let nasm_bytes = run_nasm(temp_dir.path(), &listing)
+        .map_err(|e| TestError::io(listing.display().to_string(), e))?;
+
+    if cached_bytes != nasm_bytes {
+        println!("CACHED BYTES: {:02X?}", cached_bytes);
+        println!("  NASM BYTES: {:02X?}", nasm_bytes);
+        return Err(TestError::WrongResult);
+    }
+
+    let fresh = is_listing_cache_fresh(&listing)
+        .map_err(|e| TestError::io(listing.display().to_string(), e))?;
+    if !fresh {
+        println!("{} doesn't match the listing", listing_hash_path(&listing).display());
+        return Err(TestError::WrongResult);
+    }
+
+    Ok(())
 }
 
-fn find_listing(listing: &str) -> Result<PathBuf, TestError> {
-    let mut path = get_cargo_root().map_err(|e| TestError::io("cargo".to_string(), e))?;
-    path = path.join("extras/listings").join(listing);
+// Assembles the listing with nasm and stores the result as its precompiled bytes.
+pub fn update_listing_cache(listing_name: &str) -> Result<(), TestError> {
+    let temp_dir = TempDir::new().map_err(|e| TestError::io(String::from("TempDir"), e))?;
+    let listing = find_listing(listing_name)?;
+
+    let bytes = run_nasm(temp_dir.path(), &listing)
+        .map_err(|e| TestError::io(listing.display().to_string(), e))?;
+    let cached = cached_listing_path(&listing);
+    let fresh = is_listing_cache_fresh(&listing).unwrap_or(false);
+    if fresh && std::fs::read(&cached).is_ok_and(|cached_bytes| cached_bytes == bytes) {
+        return Ok(());
+    }
+    write_listing_cache(&listing, &bytes)
+        .map_err(|e| TestError::io(cached.display().to_string(), e))
+}
+
+fn encode_all(instructions: &[intel8086::instructions::Instruction]) -> Result<Vec<u8>, TestError> {
+    let mut bytes = vec![];
+    for instruction in instructions {
+        bytes.extend(instruction.encode()?);
+    }
+    Ok(bytes)
+}
+
+fn listings_dir() -> Result<PathBuf, TestError> {
+    let path = get_cargo_root().map_err(|e| TestError::io("cargo".to_string(), e))?;
+    Ok(path.join("extras/listings"))
+}
+
+pub fn find_listing(listing: &str) -> Result<PathBuf, TestError> {
+    let path = listings_dir()?.join(listing);
     if !path.exists() {
         return Err(TestError::not_found(path.display().to_string()));
     }
     Ok(path)
 }
 
+// Returns the names of every .asm file in extras/listings, sorted.
+pub fn list_listings() -> Result<Vec<String>, TestError> {
+    let dir = listings_dir()?;
+    let entries =
+        std::fs::read_dir(&dir).map_err(|e| TestError::io(dir.display().to_string(), e))?;
+
+    let mut listings = vec![];
+    for entry in entries {
+        let path = entry
+            .map_err(|e| TestError::io(dir.display().to_string(), e))?
+            .path();
+        if path.extension().is_some_and(|extension| extension == "asm") {
+            listings.push(path.file_name().unwrap().to_string_lossy().to_string());
+        }
+    }
+
+    listings.sort();
+    Ok(listings)
+}
+
+// Whether the listing has a "; ANSWER" block describing the final state of the simulation.
+pub fn has_expectations(listing_name: &str) -> Result<bool, TestError> {
+    let listing = find_listing(listing_name)?;
+    let content = std::fs::read_to_string(&listing)
+        .map_err(|e| TestError::io(listing.display().to_string(), e))?;
+    Ok(content.lines().any(|line| line.trim() == "; ANSWER"))
+}
+
 // Poor man's clean: Cleaning a file means removing empty lines and comments.
 // That way we can diff only on the actual content.
 fn clean_asm_file(input: &str) -> String {
@@ -0,0 +1,380 @@
+use computer_enhance_rust::get_cargo_root;
+use computer_enhance_rust::intel8086::fuzzing::check_decoder_invariants;
+use computer_enhance_rust::intel8086::{self, instructions::Instruction};
+use computer_enhance_rust::nasm::run_nasm;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+// Each line of the corpus is "<hex bytes> ; <reference text>", where the reference is what GNU
+// objdump disassembles the bytes into. Lines starting with ';' are comments.
+//
+// The reference is objdump's Intel syntax rather than NASM text, so it comes from a disassembler
+// other than ours and nasm. |normalize| brings both to the same form, operand sizes included, and
+// test_corpus_reassembles checks our NASM text with nasm itself.
+const CORPUS: &str = "extras/corpus/decoder.txt";
+
+// How many random encodings we keep per opcode when generating the corpus.
+const SAMPLES_PER_OPCODE: usize = 6;
+
+struct Entry {
+    bytes: Vec<u8>,
+    reference: String,
+}
+
+// Differential check: we have to decode every sample into what the reference disassembler does.
+#[test]
+fn test_corpus_decodes() {
+    for entry in load_corpus() {
+        let instruction = Instruction::decode(&entry.bytes)
+            .unwrap_or_else(|e| panic!("{:02X?}: {}", entry.bytes, e));
+        assert_eq!(instruction.len(), entry.bytes.len(), "{:02X?}", entry.bytes);
+
+        let asm = instruction.to_string();
+        assert_eq!(
+            normalize(&asm),
+            normalize(&entry.reference),
+            "{:02X?}: \"{}\" vs \"{}\"",
+            entry.bytes,
+            asm,
+            entry.reference
+        );
+    }
+}
+
+#[test]
+fn test_corpus_covers_every_opcode() {
+    let covered: BTreeSet<u8> = load_corpus().iter().map(|e| e.bytes[0]).collect();
+    let decodable: BTreeSet<u8> = (0..=u8::MAX)
+        .filter(|opcode| !sample_encodings(*opcode, 1, |_| true).is_empty())
+        .collect();
+    let missing: Vec<_> = decodable.difference(&covered).collect();
+    assert!(missing.is_empty(), "opcodes missing from the corpus: {:02X?}", missing);
+}
+
+#[test]
+fn test_corpus_invariants() {
+    let corpus = load_corpus();
+    for entry in &corpus {
+        check_decoder_invariants(&entry.bytes);
+    }
+
+    // All together as one program, so jumps land in the middle of other instructions too.
+    let program: Vec<u8> = corpus.iter().flat_map(|e| e.bytes.clone()).collect();
+    check_decoder_invariants(&program);
+}
+
+#[test]
+fn test_random_streams() {
+    let mut rng = StdRng::seed_from_u64(8086);
+    for _ in 0..4096 {
+        let len = rng.gen_range(0..64);
+        let mut bytes = vec![0u8; len];
+        rng.fill(&mut bytes[..]);
+        check_decoder_invariants(&bytes);
+    }
+}
+
+// Differential check: nasm has to assemble our text back into something that means the same.
+// cargo test --test decoder_corpus -- --ignored
+#[test]
+#[ignore = "needs nasm"]
+fn test_corpus_reassembles() {
+    let corpus: Vec<String> = load_corpus()
+        .iter()
+        .map(|entry| Instruction::decode(&entry.bytes).unwrap().to_string())
+        .collect();
+
+    let mut asm = String::from("bits 16\n\n");
+    for line in &corpus {
+        asm.push_str(line);
+        asm.push('\n');
+    }
+
+    let temp_dir = TempDir::new().unwrap();
+    let asm_file = temp_dir.path().join("corpus.asm");
+    std::fs::write(&asm_file, asm).unwrap();
+    let bytes = run_nasm(temp_dir.path(), &asm_file).unwrap();
+
+    let instructions = intel8086::disassemble(&bytes).unwrap();
+    assert_eq!(instructions.len(), corpus.len());
+    for (instruction, asm) in instructions.iter().zip(&corpus) {
+        assert_eq!(instruction.to_string(), *asm);
+    }
+}
+
+// Rewrites the corpus with samples of every opcode we decode and what objdump makes of them.
+// Needs objdump from GNU binutils in the PATH.
+// cargo test --test decoder_corpus regenerate_corpus -- --ignored --exact
+#[test]
+#[ignore]
+fn regenerate_corpus() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let mut contents = String::new();
+    contents.push_str("; Byte sequences and how the reference disassembler reads them:\n");
+    contents.push_str("; objdump -D -b binary -m i8086 -M intel\n");
+    contents.push_str("; This is objdump's Intel syntax, not NASM's. The test normalizes both.\n");
+    contents.push_str(
+        "; Regenerate with: cargo test --test decoder_corpus regenerate_corpus -- --ignored --exact\n",
+    );
+
+    for opcode in 0..=u8::MAX {
+        // The reference rejects some encodings that the 8086, and our decoder, take anyway, like
+        // C6 with a reg field other than 0. Those are left out.
+        let mut references = vec![];
+        let samples = sample_encodings(opcode, SAMPLES_PER_OPCODE, |bytes| {
+            match reference_disassemble(temp_dir.path(), bytes) {
+                Some(reference) => {
+                    references.push(reference);
+                    true
+                }
+                None => false,
+            }
+        });
+
+        for (bytes, reference) in samples.iter().zip(&references) {
+            let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            contents.push_str(&format!("{:<20} ; {}\n", bytes.join(" "), reference));
+        }
+    }
+    std::fs::write(corpus_path(), contents).unwrap();
+}
+
+// HELPERS -----------------------------------------------------------------------------------------
+
+fn corpus_path() -> PathBuf {
+    get_cargo_root().unwrap().join(CORPUS)
+}
+
+fn load_corpus() -> Vec<Entry> {
+    let contents = std::fs::read_to_string(corpus_path()).unwrap();
+
+    let mut corpus = vec![];
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+
+        let (bytes, asm) = line
+            .split_once(';')
+            .unwrap_or_else(|| panic!("malformed corpus line: {}", line));
+        let bytes = bytes
+            .split_whitespace()
+            .map(|b| u8::from_str_radix(b, 16).unwrap())
+            .collect();
+
+        corpus.push(Entry {
+            bytes,
+            reference: asm.trim().to_string(),
+        });
+    }
+
+    assert!(!corpus.is_empty());
+    corpus
+}
+
+// Up to |samples| distinct encodings starting with |opcode| that we decode and |accept| takes,
+// from random mod/reg/rm/displacement bytes. Every opcode has its own seed, so adding one doesn't
+// change the samples of the others.
+fn sample_encodings(
+    opcode: u8,
+    samples: usize,
+    mut accept: impl FnMut(&[u8]) -> bool,
+) -> Vec<Vec<u8>> {
+    let mut rng = StdRng::seed_from_u64(8086 + opcode as u64);
+
+    let mut encodings = vec![];
+    let mut seen: BTreeSet<Vec<u8>> = BTreeSet::new();
+    for _ in 0..1024 {
+        if encodings.len() == samples {
+            break;
+        }
+
+        let mut bytes = [0u8; 6];
+        rng.fill(&mut bytes[..]);
+        bytes[0] = opcode;
+
+        let Ok(instruction) = Instruction::decode(&bytes) else {
+            continue;
+        };
+
+        let bytes = bytes[..instruction.len()].to_vec();
+        if seen.insert(bytes.clone()) && accept(&bytes) {
+            encodings.push(bytes);
+        }
+    }
+    encodings
+}
+
+// What objdump disassembles |bytes| into, or None if it doesn't take them. It has to read them as
+// one instruction, or we don't agree on the length.
+fn reference_disassemble(dir: &Path, bytes: &[u8]) -> Option<String> {
+    let path = dir.join("sample.bin");
+    std::fs::write(&path, bytes).unwrap();
+
+    let output = Command::new("objdump")
+        .args(["-D", "-b", "binary", "-m", "i8086", "-M", "intel"])
+        .arg(&path)
+        .output()
+        .expect("running objdump");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // "   0:\t8b 4e fe             \tmov    cx,WORD PTR [bp-0x2]"
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let instructions: Vec<(&str, &str)> = stdout
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let address = fields.next()?.trim().strip_suffix(':')?;
+            Some((address, fields.nth(1)?.trim()))
+        })
+        .collect();
+
+    // objdump also knows instructions of later CPUs, like xabort for C6 F8.
+    let rejected = ["(bad)", "xabort", "xbegin"];
+    if instructions
+        .first()
+        .is_some_and(|(_, text)| rejected.iter().any(|r| text.starts_with(r)))
+    {
+        return None;
+    }
+
+    assert_eq!(
+        instructions.len(),
+        1,
+        "{:02X?}: we disagree on the length: {:?}",
+        bytes,
+        instructions
+    );
+    Some(
+        instructions[0]
+            .1
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+// Our text and the reference say the same thing differently: "mov cx, [bp + 65534]" is
+// "mov cx,WORD PTR [bp-0x2]", and "jnb $+8+0" is "jae 0x8". Both become "mov cx,word [bp+65534]"
+// and "jae 8". Numbers are 16 bits, and jump targets are relative to the instruction. Memory
+// operands always get a size: the one written out, or else the one of the register next to them.
+fn normalize(asm: &str) -> String {
+    let asm = asm.trim().to_lowercase();
+    let (mnemonic, operands) = asm.split_once(' ').unwrap_or((&asm, ""));
+
+    let mnemonic = match mnemonic {
+        "jnb" => "jae",
+        "jnbe" => "ja",
+        "jnl" => "jge",
+        "jnle" => "jg",
+        "loopnz" => "loopne",
+        "loopz" => "loope",
+        mnemonic => mnemonic,
+    };
+
+    let operands: Vec<&str> = operands
+        .split(',')
+        .map(str::trim)
+        .filter(|operand| !operand.is_empty())
+        .collect();
+
+    // The shift count in cl says nothing about the size of what is shifted.
+    let shift = ["rol", "ror", "rcl", "rcr", "shl", "sal", "shr", "sar"].contains(&mnemonic);
+    let implied_size = operands
+        .iter()
+        .filter(|operand| !(shift && **operand == "cl"))
+        .find_map(|operand| register_size(operand));
+
+    let operands: Vec<String> = operands
+        .into_iter()
+        .map(|operand| normalize_operand(operand, implied_size))
+        .collect();
+    format!("{} {}", mnemonic, operands.join(","))
+}
+
+fn normalize_operand(operand: &str, implied_size: Option<&str>) -> String {
+    let mut operand = operand.trim();
+    let mut size = implied_size;
+    for (prefix, name) in [
+        ("byte ptr", "byte"),
+        ("word ptr", "word"),
+        ("byte", "byte"),
+        ("word", "word"),
+    ] {
+        if let Some(rest) = operand.strip_prefix(prefix) {
+            operand = rest.trim();
+            size = Some(name);
+            break;
+        }
+    }
+
+    // The reference has direct addresses as "ds:0x1234".
+    let memory = operand
+        .strip_prefix("ds:")
+        .or_else(|| operand.strip_prefix('[').and_then(|o| o.strip_suffix(']')));
+    if let Some(address) = memory {
+        let (registers, displacement) = terms(address);
+        let address = match (registers.is_empty(), displacement) {
+            (false, 0) => format!("[{}]", registers.join("+")),
+            (false, _) => format!("[{}+{}]", registers.join("+"), displacement),
+            (true, _) => format!("[{}]", displacement),
+        };
+        return match size {
+            Some(size) => format!("{} {}", size, address),
+            None => address,
+        };
+    }
+
+    if let Some(relative) = operand.strip_prefix('$') {
+        return terms(relative).1.to_string();
+    }
+
+    match terms(operand) {
+        (registers, _) if !registers.is_empty() => operand.to_string(),
+        (_, value) => value.to_string(),
+    }
+}
+
+fn register_size(operand: &str) -> Option<&'static str> {
+    const BYTE: [&str; 8] = ["al", "cl", "dl", "bl", "ah", "ch", "dh", "bh"];
+    const WORD: [&str; 12] = [
+        "ax", "cx", "dx", "bx", "sp", "bp", "si", "di", "es", "cs", "ss", "ds",
+    ];
+    if BYTE.contains(&operand) {
+        Some("byte")
+    } else if WORD.contains(&operand) {
+        Some("word")
+    } else {
+        None
+    }
+}
+
+// "bp + si - 0x2" -> (["bp", "si"], 65534)
+fn terms(expression: &str) -> (Vec<String>, i64) {
+    let expression = expression.replace(' ', "").replace('-', "+-");
+
+    let mut registers = vec![];
+    let mut constant: i64 = 0;
+    for term in expression.split('+').filter(|term| !term.is_empty()) {
+        let (negative, digits) = match term.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, term),
+        };
+        let value = match digits.strip_prefix("0x") {
+            Some(hex) => i64::from_str_radix(hex, 16).ok(),
+            None => digits.parse().ok(),
+        };
+
+        match value {
+            Some(value) if negative => constant -= value,
+            Some(value) => constant += value,
+            None => registers.push(term.to_string()),
+        }
+    }
+
+    (registers, constant.rem_euclid(0x10000))
+}
@@ -0,0 +1,100 @@
+use computer_enhance_rust::get_cargo_root;
+use computer_enhance_rust::json;
+use computer_enhance_rust::json::stream::StreamParser;
+use std::path::PathBuf;
+
+// Named like JSONTestSuite: y_ files must be accepted, n_ files rejected, and i_ files can go either
+// way, as long as the parser doesn't crash.
+const CORPUS: &str = "tests/json_corpus";
+
+#[test]
+fn test_must_accept() {
+    for (name, bytes) in load_corpus("y_") {
+        assert!(json::parse_strict(&bytes).is_ok(), "{}", name);
+        assert!(json::parse(&bytes).is_ok(), "{}", name);
+    }
+}
+
+#[test]
+fn test_must_reject() {
+    for (name, bytes) in load_corpus("n_") {
+        assert!(json::parse_strict(&bytes).is_err(), "{}", name);
+    }
+}
+
+#[test]
+fn test_implementation_defined() {
+    for (_, bytes) in load_corpus("i_") {
+        let _ = json::parse_strict(&bytes);
+    }
+}
+
+// Keeps the corpus honest: serde_json has to agree on what is valid.
+#[test]
+fn test_corpus_agrees_with_serde() {
+    for (name, bytes) in load_corpus("y_") {
+        let result = serde_json::from_slice::<serde_json::Value>(&bytes);
+        assert!(result.is_ok(), "{}: {:?}", name, result);
+    }
+
+    for (name, bytes) in load_corpus("n_") {
+        let result = serde_json::from_slice::<serde_json::Value>(&bytes);
+        assert!(result.is_err(), "{}: {:?}", name, result);
+    }
+}
+
+// The default parser lets trailing commas through, and nothing else.
+#[test]
+fn test_lenient_trailing_commas() {
+    for (name, bytes) in load_corpus("n_") {
+        let lenient = name.ends_with("_trailing_comma.json");
+        assert_eq!(json::parse(&bytes).is_ok(), lenient, "{}", name);
+    }
+}
+
+// The stream parser has to agree with |parse_strict|, even reading through a small buffer.
+#[test]
+fn test_stream_agrees_with_parse() {
+    for (name, bytes) in load_corpus("") {
+        let mut parser = StreamParser::with_capacity(&bytes[..], 256, true);
+        let streamed = loop {
+            match parser.next_event() {
+                Ok(Some(_)) => continue,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+
+        match (streamed, json::parse_strict(&bytes)) {
+            (Ok(()), Ok(_)) => {}
+            // The snippets can differ when the line doesn't fit in the buffer.
+            (Err(streamed), Err(parsed)) => assert_eq!(
+                (streamed.kind, streamed.offset, streamed.line, streamed.column),
+                (parsed.kind, parsed.offset, parsed.line, parsed.column),
+                "{}",
+                name
+            ),
+            (streamed, parsed) => panic!("{}: {:?} vs {:?}", name, streamed, parsed),
+        }
+    }
+}
+
+// HELPERS -----------------------------------------------------------------------------------------
+
+// Returns (file name, contents) of every file in the corpus starting with |prefix|.
+fn load_corpus(prefix: &str) -> Vec<(String, Vec<u8>)> {
+    let dir: PathBuf = get_cargo_root().unwrap().join(CORPUS);
+
+    let mut files = vec![];
+    for entry in std::fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        if name.starts_with(prefix) {
+            files.push((name, std::fs::read(&path).unwrap()));
+        }
+    }
+
+    assert!(!files.is_empty(), "no {} files in {}", prefix, dir.display());
+    files.sort();
+    files
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use common::error::TestError;
+use log::*;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+fn evaluate_debug_logging() {
+    static ACTIVATED: AtomicBool = AtomicBool::new(false);
+    if !ACTIVATED.swap(true, Ordering::Relaxed) {
+        let filter = "info";
+        // let filter = "debug";
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(filter)).init();
+    }
+}
+
+// Every listing in extras/listings has to disassemble into something our encoder turns back into
+// the same bytes.
+#[test]
+fn encode_listings() {
+    evaluate_debug_logging();
+
+    let listings = selected_listings();
+    run_listings("encode", &listings, common::run_encoder_test);
+}
+
+// And into asm that nasm turns back into the same bytes.
+#[test]
+#[ignore = "needs nasm"]
+fn disassemble_listings() {
+    evaluate_debug_logging();
+
+    let listings = selected_listings();
+    run_listings("disassemble", &listings, common::run_nasm_test);
+}
+
+// Listings with a "; ANSWER" block are also simulated and checked against it.
+#[test]
+fn simulate_listings() {
+    evaluate_debug_logging();
+
+    let listings: Vec<String> = selected_listings()
+        .into_iter()
+        .filter(|listing| common::has_expectations(listing).unwrap())
+        .collect();
+    run_listings("simulate", &listings, common::simulation::run_simulation_test);
+}
+
+// The tests above run from the precompiled bytes next to each listing, so they don't need nasm.
+// This makes sure those bytes are what nasm produces. Like every test that needs nasm, it only
+// runs when asked, and fails if nasm can't be found:
+// cargo test -- --ignored
+#[test]
+#[ignore = "needs nasm"]
+fn cached_listings_match_nasm() {
+    evaluate_debug_logging();
+
+    let listings = selected_listings();
+    run_listings("cache", &listings, common::check_listing_cache);
+}
+
+// Rewrites the precompiled bytes with the output of nasm. Only the ones that changed are written,
+// so it is safe to run along with the check above.
+// cargo test --test listings update_listing_cache -- --ignored --exact
+#[test]
+#[ignore = "needs nasm, rewrites extras/listings"]
+fn update_listing_cache() {
+    evaluate_debug_logging();
+
+    let listings = selected_listings();
+    run_listings("update", &listings, common::update_listing_cache);
+}
+
+// HELPERS -----------------------------------------------------------------------------------------
+
+// LISTING=listing_54 cargo test --test listings -- --nocapture
+// runs only the listings whose name contains "listing_54".
+fn selected_listings() -> Vec<String> {
+    let listings = common::list_listings().unwrap();
+    assert!(!listings.is_empty(), "no listings found");
+
+    match std::env::var("LISTING") {
+        Ok(filter) => listings
+            .into_iter()
+            .filter(|l| l.contains(&filter))
+            .collect(),
+        Err(_) => listings,
+    }
+}
+
+// Runs every listing, even after a failure, and reports them all before failing the test.
+fn run_listings(kind: &str, listings: &[String], test: fn(&str) -> Result<(), TestError>) {
+    let mut failures = vec![];
+    for listing in listings {
+        info!("Running {} {}", kind, listing);
+        match test(listing) {
+            Ok(()) => println!("PASS {} {}", kind, listing),
+            Err(e) => {
+                println!("FAIL {} {}: {}", kind, listing, e);
+                failures.push(listing.as_str());
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} listings failed ({}): {}",
+        failures.len(),
+        listings.len(),
+        kind,
+        failures.join(", ")
+    );
+}